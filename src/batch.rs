@@ -0,0 +1,146 @@
+use std::{
+   fs,
+   path::{Path, PathBuf},
+   sync::{Mutex, atomic::{AtomicUsize, Ordering}},
+   thread
+};
+
+use book::err_utils::{ErrStr,err_or};
+
+use w7a::types::{
+   formats::{Encoding, Format, encode, encoding_for, render},
+   w7a::{W7A, load_cst}
+};
+
+// w7a2jkf batch <dir> -o <outdir> converts every .w7a under <dir>, mirroring
+// the tree under <outdir>:
+
+// data/game_records/reijer_grimberger/2013-07-11-54th-oi-sen-game-1.w7a
+// -> kif/reijer_grimberger/2013-07-11-54th-oi-sen-game-1.kif
+
+// A record whose every output is newer than it is skipped, unless forced.
+// The records are shared out to a thread per core.
+
+pub struct Batch {
+   pub dir: PathBuf,
+   pub out: PathBuf,
+   pub formats: Vec<Format>,
+   pub encoding: Option<Encoding>,
+   pub force: bool
+}
+
+/// What came of a batch: how many converted, how many were unchanged, and
+/// the records that failed, with why
+pub struct Summary {
+   pub converted: usize,
+   pub unchanged: usize,
+   pub failed: Vec<(PathBuf, String)>
+}
+
+impl Summary {
+   pub fn report(&self) -> String {
+      format!("{} converted, {} unchanged, {} failed",
+              self.converted, self.unchanged, self.failed.len())
+   }
+}
+
+pub fn run_batch(batch: &Batch) -> ErrStr<Summary> {
+   if !batch.dir.is_dir() {
+      return Err(format!("Cannot read {}: no such directory",
+                         batch.dir.display()));
+   }
+   let mut records = Vec::new();
+   walk(&batch.dir, &mut records)?;
+   records.sort();
+   let next = AtomicUsize::new(0);
+   let outcomes = Mutex::new(Vec::with_capacity(records.len()));
+   let workers = thread::available_parallelism().map_or(1, |n| n.get())
+                                                 .min(records.len().max(1));
+   thread::scope(|scope| {
+      for _ in 0..workers {
+         scope.spawn(|| {
+            let claim = || records.get(next.fetch_add(1, Ordering::Relaxed));
+            while let Some(record) = claim() {
+               let outcome = convert(batch, record);
+               if let Ok(mut outcomes) = outcomes.lock() {
+                  outcomes.push((record.clone(), outcome));
+               }
+            }
+         });
+      }
+   });
+   let mut outcomes = outcomes.into_inner()
+                              .map_err(|_| "A conversion thread panicked")?;
+   outcomes.sort_by(|(a, _), (b, _)| a.cmp(b));
+   let mut summary = Summary { converted: 0, unchanged: 0, failed: Vec::new() };
+   for (record, outcome) in outcomes {
+      match outcome {
+         Ok(true) => summary.converted += 1,
+         Ok(false) => summary.unchanged += 1,
+         Err(msg) => summary.failed.push((record, msg))
+      }
+   }
+   Ok(summary)
+}
+
+// the .w7a files under dir, at any depth
+fn walk(dir: &Path, records: &mut Vec<PathBuf>) -> ErrStr<()> {
+   let entries = err_or(fs::read_dir(dir),
+                        &format!("Cannot read {}", dir.display()))?;
+   for entry in entries {
+      let path = err_or(entry, &format!("Cannot read {}", dir.display()))?.path();
+      if path.is_dir() {
+         walk(&path, records)?;
+      } else if path.extension()
+                    .is_some_and(|ext| ext.eq_ignore_ascii_case("w7a")) {
+         records.push(path);
+      }
+   }
+   Ok(())
+}
+
+// where the record goes, in the format, under the output directory
+fn target(batch: &Batch, record: &Path, format: Format) -> PathBuf {
+   let relative = record.strip_prefix(&batch.dir).unwrap_or(record);
+   batch.out.join(relative).with_extension(format.extension())
+}
+
+// a target is up to date when it was written after its record last changed
+fn up_to_date(record: &Path, target: &Path) -> bool {
+   let modified = |path: &Path| fs::metadata(path).and_then(|m| m.modified());
+   match (modified(record), modified(target)) {
+      (Ok(record), Ok(target)) => target >= record,
+      _ => false
+   }
+}
+
+// converts the record, unless its outputs are up to date: whether it converted
+fn convert(batch: &Batch, record: &Path) -> ErrStr<bool> {
+   let targets: Vec<(Format, PathBuf)> =
+      batch.formats.iter()
+                   .map(|&format| (format, target(batch, record, format)))
+                   .collect();
+   if !batch.force && targets.iter().all(|(_, t)| up_to_date(record, t)) {
+      return Ok(false);
+   }
+   write_targets(batch, record, &targets)?;
+   Ok(true)
+}
+
+fn write_targets(batch: &Batch, record: &Path, targets: &[(Format, PathBuf)])
+      -> ErrStr<()> {
+   let cst = load_cst(&record.to_string_lossy())?;
+   let game = W7A::from_cst(&cst)?;
+   for (format, path) in targets {
+      let text = render(&game, *format).map_err(|e| e.locate(&cst))?;
+      let text = if text.ends_with('\n') { text } else { format!("{text}\n") };
+      let encoding = batch.encoding.unwrap_or_else(|| encoding_for(path));
+      let bytes = encode(&text, encoding)?;
+      if let Some(parent) = path.parent() {
+         err_or(fs::create_dir_all(parent),
+                &format!("Cannot create {}", parent.display()))?;
+      }
+      err_or(fs::write(path, bytes), &format!("Cannot write {}", path.display()))?;
+   }
+   Ok(())
+}
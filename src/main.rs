@@ -1,27 +1,319 @@
+use std::{
+   fs,
+   io::{self, Write},
+   path::{Path, PathBuf},
+   process::exit,
+   str::FromStr
+};
+
+mod batch;
+
+use batch::{Batch, run_batch};
+
 use book::{
-   err_utils::ErrStr,
-   file_utils::lines_from_file,
+   err_utils::{ErrStr,err_or},
    utils::get_args
 };
 
-fn main() -> ErrStr<()> {
-   let args = get_args();
-   let filename = args.first().ok_or_else(|| usage())?;
-   let lines = lines_from_file(&filename)?;
-   let len = lines.len();
-   let v = len - 5;
-   let last5 = &lines[v..];
-   println!("I finished with {last5:?}");
-   Ok(())
+use w7a::types::{
+   formats::{Encoding, Format, encode, encoding_for, render},
+   stats::{Report, report},
+   tsume::{self, PLIES},
+   validate::{diagnostics_json, validate_file},
+   w7a::{W7A, load_cst, load_w7a},
+   writer::{canonical, read_kifu, to_w7a}
+};
+
+fn main() {
+   if let Err(msg) = run() {
+      eprintln!("w7a2jkf: {msg}");
+      exit(1);
+   }
+}
+
+// w7a2jkf <filename> ... converts; w7a2jkf fmt <filename> ... reformats;
+// w7a2jkf batch <dir> ... converts a whole tree; w7a2jkf validate <filename>
+// checks a record, converting nothing; w7a2jkf stats <filename> reports on
+// its clocks; w7a2jkf mate <filename> <move> looks for a mate after the move
+enum Command { Convert, Fmt, Batch, Validate, Stats, Mate }
+
+struct Args {
+   command: Command,
+   filename: String,
+   out: Option<String>,
+   formats: Vec<String>,  // each command reads its own formats
+   encoding: Option<Encoding>,
+   force: bool,
+   json: bool,
+   at: Option<usize>,     // mate: the move, the position after which
+   plies: Option<usize>,
+   threat: bool
+}
+
+fn run() -> ErrStr<()> {
+   let args = parse_args(&get_args())?;
+   match args.command {
+      Command::Batch => convert_tree(args),
+      Command::Validate => check_file(args),
+      _ => convert_file(args)
+   }
+}
+
+// the diagnostics go to stdout, as text or JSON; any error fails the check
+fn check_file(args: Args) -> ErrStr<()> {
+   let found = validate_file(&args.filename);
+   let errors = found.iter().filter(|err| err.is_error()).count();
+   if args.json {
+      println!("{}", diagnostics_json(&found));
+   } else {
+      for err in &found { println!("{err}\n"); }
+      println!("{}: {errors} errors, {} warnings", args.filename,
+               found.len() - errors);
+   }
+   if errors == 0 { Ok(()) }
+   else { Err(format!("{} is not valid", args.filename)) }
+}
+
+fn convert_tree(args: Args) -> ErrStr<()> {
+   let Args { filename, out, formats, encoding, force, .. } = args;
+   let out = out.ok_or("batch needs an -o <outdir> argument")?;
+   let formats = if formats.is_empty() { vec![Format::JKF] }
+                 else { formats.iter().map(|f| f.parse()).collect::<ErrStr<_>>()? };
+   let batch = Batch { dir: PathBuf::from(filename), out: PathBuf::from(out),
+                       formats, encoding, force };
+   let summary = run_batch(&batch)?;
+   for (record, msg) in &summary.failed {
+      eprintln!("{}: {msg}", record.display());
+   }
+   println!("{}", summary.report());
+   if summary.failed.is_empty() { Ok(()) }
+   else { Err(format!("{} records failed", summary.failed.len())) }
+}
+
+fn convert_file(args: Args) -> ErrStr<()> {
+   let Args { command, filename, out, formats, encoding, at, plies, threat, .. } = args;
+   if !Path::new(&filename).is_file() {
+      return Err(format!("Cannot read {filename}: no such file"));
+   }
+   let text = match command {
+      Command::Batch | Command::Validate => {
+         return Err("Neither batch nor validate converts a file".to_string())
+      },
+      Command::Fmt => canonical(&load_w7a(&filename)?)?,
+      Command::Stats => {
+         report(&load_w7a(&filename)?, one(&formats, Report::TABLE)?)?
+      },
+      Command::Mate => {
+         let n = at.ok_or("mate needs a <move> argument: 0, for the setup")?;
+         tsume::report(&load_w7a(&filename)?, n, plies.unwrap_or(PLIES), threat)?
+      },
+      Command::Convert if is_w7a(&filename) => {
+         // the converter's errors are located in the source afterwards
+         let cst = load_cst(&filename)?;
+         let format = one(&formats, Format::JKF)?;
+         render(&W7A::from_cst(&cst)?, format).map_err(|e| e.locate(&cst))?
+      },
+      Command::Convert => to_w7a(&read_kifu(&filename)?)
+                             .map_err(|e| format!("{filename}: {e}"))?
+   };
+   let text = if text.ends_with('\n') { text } else { format!("{text}\n") };
+   // no --encoding: the output file's extension decides; stdout is UTF-8
+   let encoding = encoding.unwrap_or_else(|| {
+      out.as_ref()
+         .map(|path| encoding_for(Path::new(path)))
+         .unwrap_or(Encoding::UTF8)
+   });
+   let bytes = encode(&text, encoding)?;
+   match out {
+      Some(path) => err_or(fs::write(&path, bytes),
+                           &format!("Cannot write {path}")),
+      None => err_or(io::stdout().write_all(&bytes), "Cannot write to stdout")
+   }
+}
+
+// the one --format given, or the default; only batch writes several
+fn one<T: FromStr<Err = String>>(formats: &[String], default: T) -> ErrStr<T> {
+   match formats {
+      [] => Ok(default),
+      [format] => format.parse(),
+      _ => Err("Writes one format; batch writes several".to_string())
+   }
+}
+
+// anything that isn't .w7a is KIF, KI2, CSA or JKF, to be written as w7a
+fn is_w7a(filename: &str) -> bool {
+   Path::new(filename).extension()
+                      .and_then(|ext| ext.to_str())
+                      .is_none_or(|ext| ext.eq_ignore_ascii_case("w7a"))
+}
+
+// [fmt|batch|validate|stats|mate] <filename> [<move>] [-o <outfile>]
+// [--format <format>[,<format>...]] [--force] [--json] [--plies <n>] [--threat],
+// options in any position
+fn parse_args(args: &[String]) -> ErrStr<Args> {
+   let (command, args) = match args.split_first() {
+      Some((cmd, rest)) if cmd == "fmt" => (Command::Fmt, rest),
+      Some((cmd, rest)) if cmd == "batch" => (Command::Batch, rest),
+      Some((cmd, rest)) if cmd == "validate" => (Command::Validate, rest),
+      Some((cmd, rest)) if cmd == "stats" => (Command::Stats, rest),
+      Some((cmd, rest)) if cmd == "mate" => (Command::Mate, rest),
+      _ => (Command::Convert, args)
+   };
+   let mut filename = None;
+   let mut out = None;
+   let mut formats = Vec::new();
+   let mut encoding = None;
+   let mut force = false;
+   let mut json = false;
+   let mut at = None;
+   let mut plies = None;
+   let mut threat = false;
+   let mut rest = args.iter();
+   while let Some(arg) = rest.next() {
+      match arg.as_str() {
+         "-o" => {
+            let path = rest.next().ok_or("-o needs an <outfile> argument")?;
+            out = Some(path.clone());
+         },
+         "-f" | "--format" => {
+            let fmt = rest.next().ok_or("--format needs a <format> argument")?;
+            for fmt in fmt.split(',') {
+               formats.push(fmt.trim().to_string());
+            }
+         },
+         "--force" => force = true,
+         "--json" => json = true,
+         "--threat" => threat = true,
+         "--plies" => {
+            let n = rest.next().ok_or("--plies needs a <plies> argument")?;
+            plies = Some(n.parse().map_err(|_| format!("--plies {n} is not a number"))?);
+         },
+         "-e" | "--encoding" => {
+            let enc = rest.next()
+                          .ok_or("--encoding needs an <encoding> argument")?;
+            encoding = Some(enc.parse()?);
+         },
+         _ if filename.is_none() => filename = Some(arg.clone()),
+         _ if matches!(command, Command::Mate) && at.is_none() => {
+            at = Some(arg.parse().map_err(|_| format!("Move {arg} is not a number"))?);
+         },
+         _ => {
+            usage();
+            return Err(format!("Unexpected argument {arg}"));
+         }
+      }
+   }
+   let filename = filename.ok_or_else(usage)?;
+   // an option the command makes no use of is a mistake, not to pass over
+   let (batch, validate, mate) = (matches!(command, Command::Batch),
+                                  matches!(command, Command::Validate),
+                                  matches!(command, Command::Mate));
+   let writes = match command {
+      Command::Convert => is_w7a(&filename),
+      Command::Batch | Command::Stats => true,
+      _ => false
+   };
+   let misplaced = [("--force", force && !batch),
+                    ("--json", json && !validate),
+                    ("--threat", threat && !mate),
+                    ("--plies", plies.is_some() && !mate),
+                    ("--format", !formats.is_empty() && !writes),
+                    ("-o", out.is_some() && validate),
+                    ("--encoding", encoding.is_some() && validate)];
+   if let Some((opt, _)) = misplaced.iter().find(|(_, given)| *given) {
+      let name = match command {
+         Command::Convert if is_w7a(&filename) => "converting",
+         Command::Convert => "writing w7a",
+         Command::Fmt => "fmt",
+         Command::Batch => "batch",
+         Command::Validate => "validate",
+         Command::Stats => "stats",
+         Command::Mate => "mate"
+      };
+      return Err(format!("{name} takes no {opt}"));
+   }
+   Ok(Args { command, filename, out, formats, encoding, force, json, at, plies,
+             threat })
 }
 
 fn usage() -> String {
-   println!("$ ./w7a2jkf <filename>
+   println!("$ ./w7a2jkf <filename> [-o <outfile>] [--format <format>]
+                      [--encoding <encoding>]
+$ ./w7a2jkf fmt <filename> [-o <outfile>]
+$ ./w7a2jkf batch <dir> -o <outdir> [--format <format>[,<format>...]]
+                      [--encoding <encoding>] [--force]
+$ ./w7a2jkf validate <filename> [--json]
+$ ./w7a2jkf stats <filename> [-o <outfile>] [--format table|csv|svg]
+$ ./w7a2jkf mate <filename> <move> [--plies <plies>] [--threat]
 
-Converts a Western-style Shogi game record to JKF (JSON Kifu Format)
+Converts a Western-style Shogi game record to JKF (JSON Kifu Format), or
+to KIF, KI2 or CSA; or converts a KIF, KI2, CSA or JKF record to w7a.
+With fmt, rewrites the w7a file canonically: header tags in order, moves and
+clocks in aligned columns, comments wrapped, the game itself unchanged.
+With batch, converts every .w7a file under <dir>, in parallel, to each
+<format>, mirroring the tree under <outdir>; records whose outputs are newer
+than they are skipped, unless --force.
+With validate, reads and replays the record, converting nothing, and reports
+bad moves and clocks, misnumbered and illegal moves, results the replay
+contradicts (a sennichite, jishogi or mate that isn't one), checks and mates
+marked on moves that give none, and missing header tags, as text or, with
+--json, as a JSON array of diagnostics.
+With stats, reports on the clocks: each side's longest thinks, its thinking
+time by phase and when it entered byoyomi, as a table; the thinks, move by
+move, as CSV; or the two cumulative clocks, as an SVG chart.
+With mate, looks for a mate the side to move forces after <move>, checking on
+every move, in at most <plies> plies (7, if not given); with --threat, the
+mate the side that played <move> threatens, were its opponent to pass.
 
 where:
 
-* <filename> is the path (and filename) of the w7a-formatted file");
+* <filename> is the path (and filename) of the w7a-formatted file, or of a
+  .kif, .kifu, .ki2, .csa or .jkf file to be written as w7a
+* <outfile> is where the converted record is written; stdout if omitted
+* <format> is one of kif, ki2, csa or jkf (the default), for w7a files
+* <move> is the number of the move after which mate is looked for; 0, for
+  the setup
+* <encoding> is one of utf-8, utf-8-bom or shift_jis. Without it, a .kif or
+  .ki2 <outfile> is written in Shift_JIS, anything else in UTF-8");
    "Needs <filename> argument".to_string()
 }
+
+// ----- TESTS -------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   fn parse(args: &[&str]) -> ErrStr<Args> {
+      parse_args(&args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>())
+   }
+
+   #[test]
+   fn test_options_of_the_command() {
+      assert!(parse(&["game.w7a", "--format", "kif", "-o", "game.kif"]).is_ok());
+      assert!(parse(&["batch", "games", "-o", "out", "--force", "-f", "kif,csa"]).is_ok());
+      assert!(parse(&["mate", "game.w7a", "41", "--plies", "5", "--threat"]).is_ok());
+      assert!(parse(&["validate", "game.w7a", "--json"]).is_ok());
+   }
+
+   #[test]
+   fn fail_misplaced_options() {
+      let err = |args: &[&str]| parse(args).err();
+      assert_eq!(Some("validate takes no --force".to_string()),
+                 err(&["validate", "game.w7a", "--force"]));
+      assert_eq!(Some("converting takes no --json".to_string()),
+                 err(&["game.w7a", "--json"]));
+      assert_eq!(Some("stats takes no --plies".to_string()),
+                 err(&["stats", "game.w7a", "--plies", "3"]));
+      assert_eq!(Some("validate takes no -o".to_string()),
+                 err(&["validate", "game.w7a", "-o", "out.txt"]));
+      assert_eq!(Some("validate takes no --format".to_string()),
+                 err(&["validate", "game.w7a", "--format", "kif"]));
+      assert_eq!(Some("validate takes no --encoding".to_string()),
+                 err(&["validate", "game.w7a", "-e", "shift_jis"]));
+      assert_eq!(Some("mate takes no --format".to_string()),
+                 err(&["mate", "game.w7a", "41", "--format", "kif"]));
+      assert_eq!(Some("writing w7a takes no --format".to_string()),
+                 err(&["game.kif", "--format", "jkf"]));
+   }
+}
@@ -4,9 +4,57 @@ pub mod jkf;
 /// The w7a representation used for parsing/intake
 pub mod w7a;
 
+/// The lossless concrete syntax tree of a w7a file, with source spans
+pub mod cst;
+
+/// The games of a large collection (or stdin), read one at a time
+pub mod archive;
+
+/// The errors of parsing and converting, as rustc-style diagnostics
+pub mod error;
+
+/// The board, for replaying moves
+pub mod board;
+
+/// Where a game starts: the even position, a handicap, or an SFEN
+pub mod setup;
+
+/// The KIF relative-move markers (右/左/直/上/引/寄/打)
+pub mod relative;
+
+/// Sennichite: fourfold repetition, found by hashing the positions
+pub mod repetition;
+
+/// Check and mate, as the replay finds them and as w7a marks them
+pub mod check;
+
+/// Tsume: the mate, checking on every move, a position forces
+pub mod tsume;
+
+/// Jishogi (impasse): the points of each side, and the 24- and 27-point rules
+pub mod jishogi;
+
+/// The time control, and the lints of the clocks
+pub mod clock;
+
+/// The time-usage reports: longest thinks, time by phase, byoyomi
+pub mod stats;
+
+/// The sessions of a multi-day game, split at its sealed moves
+pub mod session;
+
 /// The translator from w7a to JKF
 pub mod conv;
 
+/// KIF, KI2, CSA and JKF output, by way of JKF
+pub mod formats;
+
+/// The translator from JKF (or KIF, KI2, CSA) back to w7a
+pub mod writer;
+
+/// Validating a record by reading and replaying it, with JSON diagnostics
+pub mod validate;
+
 /// Types used across the system
 pub mod utils;
 
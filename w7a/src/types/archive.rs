@@ -0,0 +1,170 @@
+use std::{
+   fs::File,
+   io::{BufRead, BufReader}
+};
+
+use super::{
+   cst::Cst,
+   error::{ErrW7A, ErrorKind::*, W7AErr},
+   w7a::{GameEnd, W7A}
+};
+
+// A collection (an archive) of w7a games is the games, one after the other:
+
+// [Black "Habu Yoshiharu, Oi"]
+// ...
+// Resigns      07:55:00  07:59:00
+//
+// [Black "Habu Yoshiharu, Kisei"]
+// ...
+
+// A header tag, [Key "value"], after a game's first move begins the next
+// game, if it follows the result or a blank line; a bracketed comment among
+// the moves, "[Diagram 1]", is the game's, as the syntax tree has it.
+
+// Games reads the archive line by line, holding one game at a time, so an
+// archive of any size (or stdin) reads in the memory of its largest game.
+
+pub struct Games<R> {
+   reader: R,
+   name: Option<String>,
+   pending: Option<(usize, String)>, // the tag-line, and its number, that
+                                     // began the next game
+   read: usize,                      // the lines read so far
+   done: bool
+}
+
+/// The games of the archive, as they are read
+pub fn games<R: BufRead>(reader: R) -> Games<R> {
+   Games { reader, name: None, pending: None, read: 0, done: false }
+}
+
+pub fn load_games(filename: &str) -> ErrW7A<Games<BufReader<File>>> {
+   let file = File::open(filename).map_err(|e| {
+      W7AErr::new(READ, &format!("Cannot read {filename}: {e}")).in_file(filename)
+   })?;
+   Ok(Games { name: Some(filename.to_string()), ..games(BufReader::new(file)) })
+}
+
+impl<R: BufRead> Games<R> {
+   // the source of the next game, and the number of its first line
+   fn next_source(&mut self) -> ErrW7A<(usize, String)> {
+      let (first, mut source) = self.pending.take()
+                                    .unwrap_or((self.read + 1, String::new()));
+      let mut end = GameEnd::default();
+      loop {
+         let mut line = String::new();
+         let len = self.reader.read_line(&mut line).map_err(|e| {
+            let err = W7AErr::new(READ, &format!("Cannot read line {}: {e}",
+                                                 self.read + 1));
+            match &self.name { Some(name) => err.in_file(name), None => err }
+         })?;
+         if len == 0 {
+            self.done = true;
+            break;
+         }
+         self.read += 1;
+         let body = line.trim_end_matches(['\n', '\r']);
+         if end.begins_next(body) {
+            self.pending = Some((self.read, line));
+            break;
+         }
+         source.push_str(&line);
+      }
+      Ok((first, source))
+   }
+}
+
+impl<R: BufRead> Iterator for Games<R> {
+   type Item = ErrW7A<W7A>;
+
+   fn next(&mut self) -> Option<ErrW7A<W7A>> {
+      if self.done && self.pending.is_none() { return None; }
+      match self.next_source() {
+         Err(err) => Some(Err(err)),
+         Ok((_, source)) if source.trim().is_empty() => None,
+         Ok((first, source)) => {
+            let cst = Cst::parse(source).numbered_from(first);
+            let cst = match &self.name { Some(name) => cst.named(name), None => cst };
+            Some(W7A::from_cst(&cst))
+         }
+      }
+   }
+}
+
+// ----- TESTS -------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   use std::io::Cursor;
+
+   const ARCHIVE: &str = "[Black \"Habu\"]\n\
+                          [White \"Namekata\"]\n\
+                          \n\
+                          1.P7g-7f     00:00:00  00:00:00\n\
+                          Resigns      00:00:00  00:00:00\n\
+                          \n\
+                          [Black \"Watanabe\"]\n\
+                          A comment\n\
+                          1.P2g-2f     00:00:00  00:00:00\n\
+                          2.P3c-3d     00:00:00  00:00:00\n\
+                          \n\
+                          [Black \"Hane\"]\n\
+                          1.Q7g-7f     00:00:00  00:00:00\n";
+
+   #[test]
+   fn test_games_one_by_one() {
+      let read: Vec<ErrW7A<W7A>> = games(Cursor::new(ARCHIVE)).collect();
+      assert_eq!(3, read.len());
+      let blacks: Vec<Option<&str>> =
+         read.iter().map(|g| g.as_ref().ok().and_then(|g| g.tag("Black"))).collect();
+      assert_eq!(vec![Some("Habu"), Some("Watanabe"), None], blacks);
+      assert_eq!(Some("A comment"), read[1].as_ref().ok().and_then(|g| g.comment()));
+      assert_eq!(2, read[1].as_ref().map(|g| g.moves().len()).unwrap_or(0));
+   }
+
+   #[test]
+   fn test_bracketed_comment_in_the_moves() -> Result<(), String> {
+      let archive = "[Black \"Habu\"]\n\
+                     1.P7g-7f     00:00:00  00:00:00\n\
+                     [Diagram 1]\n\
+                     2.P3c-3d     00:00:00  00:00:00\n\
+                     \n\
+                     [Diagram 2]\n\
+                     \n\
+                     3.P2g-2f     00:00:00  00:00:00\n\
+                     \n\
+                     [Black \"Watanabe\"]\n\
+                     1.P2g-2f     00:00:00  00:00:00\n";
+      let read: Vec<W7A> = games(Cursor::new(archive)).collect::<ErrW7A<_>>()?;
+      assert_eq!(2, read.len());
+      assert_eq!(3, read[0].moves().len());
+      assert_eq!(Some("Watanabe"), read[1].tag("Black"));
+      Ok(())
+   }
+
+   #[test]
+   fn test_errors_numbered_in_the_archive() {
+      let last = games(Cursor::new(ARCHIVE)).last();
+      let err = last.and_then(Result::err).expect("the third game is malformed");
+      assert_eq!((BADMOVE, Some(13)), (err.kind, err.line()));
+   }
+
+   #[test]
+   fn test_one_game_file() -> Result<(), String> {
+      let dir = "../data/game_records/reijer_grimberger";
+      let read: Vec<W7A> =
+         load_games(&format!("{dir}/2013-07-11-54th-oi-sen-game-1.w7a"))?
+            .collect::<ErrW7A<_>>()?;
+      assert_eq!(1, read.len());
+      assert_eq!(83, read[0].moves().len());
+      Ok(())
+   }
+
+   #[test]
+   fn test_empty_archive() {
+      assert_eq!(0, games(Cursor::new("\n\n")).count());
+   }
+}
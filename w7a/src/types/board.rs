@@ -0,0 +1,645 @@
+use std::sync::OnceLock;
+
+use chrono::Duration;
+
+use book::err_utils::ErrStr;
+
+use super::w7a::{
+   Color, Color::*, Move, Piece, PieceType, PieceType::*, Position
+};
+
+// The board replays the moves of a game so we know what was captured (the
+// w7a notation says only that something was, with the 'x').
+
+// ----- SQUARES -------------------------------------------------------
+
+/// (file, rank), each 1 - 9; rank 1 is "a", White's back rank
+pub type Square = (usize, usize);
+
+pub fn square(pos: &Position) -> ErrStr<Square> {
+   let sq = (pos.x, pos.rank());
+   if on_board(sq.0 as i32, sq.1 as i32) {
+      Ok(sq)
+   } else {
+      Err(format!("Square {pos} is off the board"))
+   }
+}
+
+pub fn position((x, y): Square) -> Position {
+   let rank = (b'a' + (y as u8) - 1) as char;
+   Position { x, y: rank.to_string() }
+}
+
+fn on_board(x: i32, y: i32) -> bool {
+   (1..=9).contains(&x) && (1..=9).contains(&y)
+}
+
+// ----- OCCUPANTS -------------------------------------------------------
+
+/// A piece on the board belongs to one side or the other
+#[derive(Debug,Clone,PartialEq)]
+pub struct Occupant { pub color: Color, pub piece: Piece }
+
+pub fn side(c: &Color) -> usize {
+   match c { BLACK => 0, WHITE => 1 }
+}
+
+pub fn opponent(c: &Color) -> Color {
+   match c { BLACK => WHITE, WHITE => BLACK }
+}
+
+/// Black advances toward rank "a", White toward rank "i"
+pub fn forward(c: &Color) -> i32 {
+   match c { BLACK => -1, WHITE => 1 }
+}
+
+// ----- BOARD -------------------------------------------------------
+
+#[derive(Debug,Clone,PartialEq)]
+pub struct Board {
+   squares: [[Option<Occupant>; 9]; 9], // squares[file - 1][rank - 1]
+   hands: [[usize; 8]; 2],               // hands[side][PieceType]
+   pub turn: Color                       // the side to move
+}
+
+impl Default for Board {
+   /// The even-game (hirate) starting position
+   fn default() -> Self {
+      let mut board = Board::empty();
+      let back = [LANCE, KNIGHT, SILVER, GOLD, KING, GOLD, SILVER, KNIGHT, LANCE];
+      for (i, piece) in back.into_iter().enumerate() {
+         board.put((i + 1, 1), WHITE, piece);
+         board.put((i + 1, 9), BLACK, piece);
+      }
+      for x in 1..=9 {
+         board.put((x, 3), WHITE, PAWN);
+         board.put((x, 7), BLACK, PAWN);
+      }
+      board.put((8, 2), WHITE, ROOK);
+      board.put((2, 2), WHITE, BISHOP);
+      board.put((2, 8), BLACK, ROOK);
+      board.put((8, 8), BLACK, BISHOP);
+      board
+   }
+}
+
+impl Board {
+   pub fn empty() -> Self {
+      Board { squares: Default::default(), hands: [[0; 8]; 2], turn: BLACK }
+   }
+
+   pub(crate) fn put(&mut self, sq: Square, color: Color, piece: PieceType) {
+      self.set(sq, Some(Occupant { color, piece: Piece { promoted: false, piece } }));
+   }
+
+   /// Puts the occupant on the square, or clears it; off the board, nothing
+   pub(crate) fn set(&mut self, (x, y): Square, occupant: Option<Occupant>) {
+      if let Some(sq) = self.squares.get_mut(x.wrapping_sub(1))
+                                   .and_then(|file| file.get_mut(y.wrapping_sub(1))) {
+         *sq = occupant;
+      }
+   }
+
+   pub(crate) fn give(&mut self, c: &Color, piece: PieceType, count: usize) {
+      self.hands[side(c)][piece as usize] += count;
+   }
+
+   /// What stands on the square; nothing, off the board
+   pub fn at(&self, (x, y): Square) -> Option<&Occupant> {
+      self.squares.get(x.wrapping_sub(1))
+                  .and_then(|file| file.get(y.wrapping_sub(1)))
+                  .and_then(Option::as_ref)
+   }
+
+   pub fn in_hand(&self, c: &Color, piece: PieceType) -> usize {
+      self.hands[side(c)][piece as usize]
+   }
+
+   /// All the occupied squares, file by file
+   pub fn occupied(&self) -> Vec<(Square, &Occupant)> {
+      let mut ans = Vec::new();
+      for x in 1..=9 {
+         for y in 1..=9 {
+            if let Some(occ) = self.at((x, y)) { ans.push(((x, y), occ)); }
+         }
+      }
+      ans
+   }
+
+   /// Can the piece on `from` move to `to`, given what is in between?
+   /// (Whatever stands on `to` is not considered.)
+   pub fn reaches(&self, from: Square, to: Square) -> bool {
+      self.at(from).is_some_and(|occ| {
+         let (dx, dy) = (to.0 as i32 - from.0 as i32, to.1 as i32 - from.1 as i32);
+         steps(occ).contains(&(dx, dy))
+            || slides(occ).into_iter().any(|dir| self.slides_to(from, dir, to))
+      })
+   }
+
+   fn slides_to(&self, from: Square, (dx, dy): (i32, i32), to: Square) -> bool {
+      let (mut x, mut y) = (from.0 as i32 + dx, from.1 as i32 + dy);
+      while on_board(x, y) {
+         let sq = (x as usize, y as usize);
+         if sq == to { return true; }
+         if self.at(sq).is_some() { return false; }
+         x += dx;
+         y += dy;
+      }
+      false
+   }
+
+   /// Is the side's king attacked?
+   pub fn in_check(&self, c: &Color) -> bool {
+      let occupied = self.occupied();
+      let king = occupied.iter()
+                         .find(|(_, occ)| occ.color == *c && occ.piece.piece == KING);
+      king.is_some_and(|(k, _)| {
+         occupied.iter().any(|(sq, occ)| occ.color != *c && self.reaches(*sq, *k))
+      })
+   }
+
+   /// The position's Zobrist hash: of the board, the hands and the side to move
+   pub fn hash(&self) -> u64 {
+      let keys = keys();
+      let mut hash = if self.turn == WHITE { keys.white } else { 0 };
+      for ((x, y), occ) in self.occupied() {
+         let piece = occ.piece.piece as usize * 2 + occ.piece.promoted as usize;
+         hash ^= keys.squares[(x - 1) * 9 + y - 1][side(&occ.color)][piece];
+      }
+      for (s, hand) in self.hands.iter().enumerate() {
+         for (piece, count) in hand.iter().enumerate() {
+            hash ^= keys.hands[s][piece][(*count).min(MOST_IN_HAND)];
+         }
+      }
+      hash
+   }
+
+   /// Plays the move, of the side to move, on the board, returning the
+   /// captured piece, if any. A move against the rules is an Err, and leaves
+   /// the board as it was.
+   pub fn apply(&mut self, mov: &Move) -> ErrStr<Option<Piece>> {
+      let mut after = self.clone();
+      let captured = after.advance(mov)?;
+      if let Some(why) = self.refusal(mov, &after) {
+         return Err(format!("Move {}: {why}", mov.n));
+      }
+      *self = after;
+      Ok(captured)
+   }
+
+   // plays a move known to be legal, one of the legal_moves, or to be
+   // checked after: the piece moves, and nothing else is asked of it
+   pub(crate) fn advance(&mut self, mov: &Move) -> ErrStr<Option<Piece>> {
+      let captured = self.play(mov)?;
+      self.turn = opponent(&self.turn);
+      Ok(captured)
+   }
+
+   // why the move, played to `after`, is against the rules, if it is
+   fn refusal(&self, mov: &Move, after: &Board) -> Option<String> {
+      let c = &self.turn;
+      let to = square(&mov.to).ok()?;
+      let piece = mov.piece.piece;
+      match &mov.from {
+         None if piece == PAWN && self.pawn_on_file(c, to.0) => {
+            return Some(format!("a second pawn on file {} (nifu)", to.0));
+         },
+         None if stranded(c, piece, to.1) => {
+            return Some(format!("{piece:?} dropped on {} can never move", mov.to));
+         },
+         None => {},
+         Some(pos) => {
+            let from = square(pos).ok()?;
+            let options = promotions(self.at(from)?, from, to);
+            let promotes = mov.promote == Some(true);
+            if promotes && !options.contains(&Some(true)) {
+               return Some(format!("{piece:?} cannot promote moving from {pos} to {}",
+                                   mov.to));
+            }
+            if !promotes && options == [Some(true)] {
+               return Some(format!("{piece:?} on {} can never move unpromoted",
+                                   mov.to));
+            }
+         }
+      }
+      if after.in_check(c) {
+         Some("leaves the mover's king in check".to_string())
+      } else if !self.legal(mov, true) {
+         Some("a pawn dropped may not mate".to_string())
+      } else {
+         None
+      }
+   }
+
+   fn play(&mut self, mov: &Move) -> ErrStr<Option<Piece>> {
+      let c = self.turn.clone();
+      let n = mov.n;
+      let to = square(&mov.to)?;
+      let target = self.at(to).cloned();
+      if target.as_ref().is_some_and(|occ| occ.color == c) {
+         return Err(format!("Move {n}: {} is occupied by the mover's own piece",
+                            mov.to));
+      }
+      match &mov.from {
+         None => {
+            let piece = mov.piece.piece;
+            if target.is_some() {
+               return Err(format!("Move {n}: cannot drop onto occupied {}",
+                                  mov.to));
+            }
+            if mov.piece.promoted || self.in_hand(&c, piece) == 0 {
+               return Err(format!("Move {n}: no {piece:?} in hand to drop"));
+            }
+            self.hands[side(&c)][piece as usize] -= 1;
+            self.put(to, c, piece);
+            Ok(None)
+         },
+         Some(pos) => {
+            let from = square(pos)?;
+            let mover = self.at(from).cloned()
+                            .ok_or(format!("Move {n}: no piece on {pos}"))?;
+            if mover.color != c || mover.piece != mov.piece {
+               return Err(format!("Move {n}: {:?} on {pos} is not the mover's \
+                                   {:?}", mover.piece, mov.piece));
+            }
+            if !self.reaches(from, to) {
+               return Err(format!("Move {n}: {:?} cannot move from {pos} to {}",
+                                  mov.piece.piece, mov.to));
+            }
+            if mov.capture != target.is_some() {
+               return Err(format!("Move {n}: capture on {} does not match \
+                                   the board", mov.to));
+            }
+            let promoted = mover.piece.promoted || mov.promote == Some(true);
+            let piece = Piece { promoted, piece: mover.piece.piece };
+            self.squares[from.0 - 1][from.1 - 1] = None;
+            self.squares[to.0 - 1][to.1 - 1] = Some(Occupant { color: c.clone(), piece });
+            let captured = target.map(|occ| occ.piece);
+            if let Some(taken) = &captured {
+               self.hands[side(&c)][taken.piece as usize] += 1;
+            }
+            Ok(captured)
+         }
+      }
+   }
+
+   /// The moves the side to move may play, promoting where it may, and
+   /// dropping; none that leaves its own king in check, puts a second pawn
+   /// on a file, strands a piece where it can never move again, or mates
+   /// with a dropped pawn. The moves are unnumbered and untimed.
+   pub fn legal_moves(&self) -> Vec<Move> {
+      self.candidates().into_iter().filter(|mov| self.legal(mov, true)).collect()
+   }
+
+   /// Is the side to move mated: in check, with no move out of it?
+   pub fn is_mate(&self) -> bool {
+      self.in_check(&self.turn)
+         && !self.candidates().iter().any(|mov| self.legal(mov, true))
+   }
+
+   // the moves of the side to move, whatever they do to its own king
+   fn candidates(&self) -> Vec<Move> {
+      let c = &self.turn;
+      let mut moves = Vec::new();
+      for (from, occ) in self.occupied() {
+         if occ.color != *c { continue; }
+         for to in self.targets(from, occ) {
+            let capture = self.at(to).is_some();
+            for promote in promotions(occ, from, to) {
+               moves.push(self.mk_move(occ.piece.clone(), Some(from), to, capture, promote));
+            }
+         }
+      }
+      for piece in IN_HAND {
+         if self.in_hand(c, piece) == 0 { continue; }
+         for (x, y) in (1..=9).flat_map(|x| (1..=9).map(move |y| (x, y))) {
+            if self.at((x, y)).is_none() && !stranded(c, piece, y)
+                  && !(piece == PAWN && self.pawn_on_file(c, x)) {
+               let piece = Piece { promoted: false, piece };
+               moves.push(self.mk_move(piece, None, (x, y), false, None));
+            }
+         }
+      }
+      moves
+   }
+
+   // the squares the piece on `from` moves to: empty, or the enemy's
+   fn targets(&self, from: Square, occ: &Occupant) -> Vec<Square> {
+      let open = |x: i32, y: i32| {
+         on_board(x, y)
+            && self.at((x as usize, y as usize)).is_none_or(|o| o.color != occ.color)
+      };
+      let mut ans = Vec::new();
+      for (dx, dy) in steps(occ) {
+         let (x, y) = (from.0 as i32 + dx, from.1 as i32 + dy);
+         if open(x, y) { ans.push((x as usize, y as usize)); }
+      }
+      for (dx, dy) in slides(occ) {
+         let (mut x, mut y) = (from.0 as i32 + dx, from.1 as i32 + dy);
+         while open(x, y) {
+            ans.push((x as usize, y as usize));
+            if self.at((x as usize, y as usize)).is_some() { break; }
+            x += dx;
+            y += dy;
+         }
+      }
+      ans
+   }
+
+   fn pawn_on_file(&self, c: &Color, x: usize) -> bool {
+      (1..=9).any(|y| self.at((x, y)).is_some_and(|occ| {
+         occ.color == *c && occ.piece == Piece { promoted: false, piece: PAWN }
+      }))
+   }
+
+   fn mk_move(&self, piece: Piece, from: Option<Square>, to: Square, capture: bool,
+              promote: Option<bool>) -> Move {
+      Move { n: 0, color: self.turn.clone(), piece, from: from.map(position),
+             to: position(to), capture, drop: from.is_none(), promote,
+             total_time: Duration::zero(), other_time: Duration::zero(), comment: None }
+   }
+
+   // the move leaves the mover's king safe and, with `uchifuzume`, does not
+   // mate with a dropped pawn (the defender's replies are not asked that,
+   // as a pawn dropped in between cannot mate)
+   fn legal(&self, mov: &Move, uchifuzume: bool) -> bool {
+      let mut after = self.clone();
+      if after.advance(mov).is_err() || after.in_check(&self.turn) { return false; }
+      !(uchifuzume && mov.drop && mov.piece.piece == PAWN
+           && after.in_check(&after.turn)
+           && !after.candidates().iter().any(|reply| after.legal(reply, false)))
+   }
+}
+
+// ----- hashing -------------------------------------------------
+
+// Zobrist hashing: a random key for each piece, of each side, on each square;
+// for each count of each piece in each hand; and for White to move. A
+// position hashes to the xor of its keys, so the same position, however it
+// came about, hashes the same.
+
+const MOST_IN_HAND: usize = 18;  // the pawns
+
+struct Keys {
+   squares: [[[u64; 16]; 2]; 81],                // [square][side][piece, promoted]
+   hands: [[[u64; MOST_IN_HAND + 1]; 8]; 2],     // [side][piece][count]
+   white: u64
+}
+
+fn keys() -> &'static Keys {
+   static KEYS: OnceLock<Keys> = OnceLock::new();
+   KEYS.get_or_init(|| {
+      // splitmix64, from a fixed seed: the same keys, run after run
+      let mut seed: u64 = 0x7761_3761_6a6b_6621;
+      let mut next = || {
+         seed = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+         let mut z = seed;
+         z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+         z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+         z ^ (z >> 31)
+      };
+      let mut keys = Keys { squares: [[[0; 16]; 2]; 81],
+                            hands: [[[0; MOST_IN_HAND + 1]; 8]; 2], white: 0 };
+      keys.squares.iter_mut().flatten().flatten().for_each(|key| *key = next());
+      // none in hand is no key, so that an empty hand changes nothing
+      keys.hands.iter_mut().flatten()
+                .for_each(|counts| counts.iter_mut().skip(1).for_each(|key| *key = next()));
+      keys.white = next();
+      keys
+   })
+}
+
+// ----- piece movement -------------------------------------------------
+
+const GOLD_STEPS: [(i32, i32); 6] = [(0, 1), (-1, 1), (1, 1), (-1, 0), (1, 0), (0, -1)];
+const SILVER_STEPS: [(i32, i32); 5] = [(0, 1), (-1, 1), (1, 1), (-1, -1), (1, -1)];
+const DIAGONALS: [(i32, i32); 4] = [(1, 1), (1, -1), (-1, 1), (-1, -1)];
+const ORTHOGONALS: [(i32, i32); 4] = [(0, 1), (0, -1), (1, 0), (-1, 0)];
+
+// the single steps a piece takes, oriented to its side
+fn steps(occ: &Occupant) -> Vec<(i32, i32)> {
+   let f = forward(&occ.color);
+   let orient = |v: &[(i32, i32)]| -> Vec<(i32, i32)> {
+      v.iter().map(|(dx, dy)| (*dx, dy * f)).collect()
+   };
+   match (occ.piece.piece, occ.piece.promoted) {
+      (KING, _) => [DIAGONALS, ORTHOGONALS].concat(),
+      (GOLD, _) | (PAWN, true) | (LANCE, true) | (KNIGHT, true)
+         | (SILVER, true) => orient(&GOLD_STEPS),
+      (SILVER, false) => orient(&SILVER_STEPS),
+      (KNIGHT, false) => orient(&[(-1, 2), (1, 2)]),
+      (PAWN, false) => orient(&[(0, 1)]),
+      (BISHOP, true) => ORTHOGONALS.to_vec(),
+      (ROOK, true) => DIAGONALS.to_vec(),
+      (LANCE, false) | (BISHOP, false) | (ROOK, false) => Vec::new()
+   }
+}
+
+const IN_HAND: [PieceType; 7] = [PAWN, LANCE, KNIGHT, SILVER, GOLD, BISHOP, ROOK];
+
+// the enemy's camp: ranks a to c for Black, g to i for White
+fn in_camp(c: &Color, y: usize) -> bool {
+   match c { BLACK => y <= 3, WHITE => y >= 7 }
+}
+
+// would the unpromoted piece, on rank y, never move again?
+fn stranded(c: &Color, piece: PieceType, y: usize) -> bool {
+   let far = match c { BLACK => y, WHITE => 10 - y };  // ranks from the far side
+   match piece { PAWN | LANCE => far == 1, KNIGHT => far <= 2, _ => false }
+}
+
+// a piece may promote moving into, within or out of the enemy's camp; it
+// must, where it would be stranded
+fn promotions(occ: &Occupant, from: Square, to: Square) -> Vec<Option<bool>> {
+   let piece = occ.piece.piece;
+   let may = !occ.piece.promoted && !matches!(piece, GOLD | KING)
+             && (in_camp(&occ.color, from.1) || in_camp(&occ.color, to.1));
+   if !may {
+      vec![None]
+   } else if stranded(&occ.color, piece, to.1) {
+      vec![Some(true)]
+   } else {
+      vec![Some(true), Some(false)]
+   }
+}
+
+// the directions a piece slides in, oriented to its side
+fn slides(occ: &Occupant) -> Vec<(i32, i32)> {
+   match (occ.piece.piece, occ.piece.promoted) {
+      (LANCE, false) => vec![(0, forward(&occ.color))],
+      (BISHOP, _) => DIAGONALS.to_vec(),
+      (ROOK, _) => ORTHOGONALS.to_vec(),
+      _ => Vec::new()
+   }
+}
+
+// ----- TESTS -------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   use crate::types::setup::from_sfen;
+
+   fn pos(x: usize, y: &str) -> Position { Position { x, y: y.to_string() } }
+
+   fn mk_move(n: usize, piece: PieceType, from: Option<Position>,
+              to: Position, capture: bool) -> Move {
+      let color = if n % 2 == 1 { BLACK } else { WHITE };
+      Move { n, color, piece: Piece { promoted: false, piece }, drop: from.is_none(),
+             from, to, capture, promote: None,
+             total_time: Duration::zero(), other_time: Duration::zero(),
+             comment: None }
+   }
+
+   #[test]
+   fn test_hirate() {
+      let board = Board::default();
+      assert_eq!(40, board.occupied().len());
+      let king = board.at((5, 9)).unwrap();
+      assert_eq!(BLACK, king.color);
+      assert_eq!(KING, king.piece.piece);
+      assert_eq!(ROOK, board.at((8, 2)).unwrap().piece.piece);
+   }
+
+   #[test]
+   fn test_square() -> ErrStr<()> {
+      assert_eq!((7, 7), square(&pos(7, "g"))?);
+      assert_eq!(pos(2, "b"), position((2, 2)));
+      Ok(())
+   }
+
+   #[test]
+   fn test_pawn_push() -> ErrStr<()> {
+      let mut board = Board::default();
+      let taken = board.apply(&mk_move(1, PAWN, Some(pos(7, "g")), pos(7, "f"), false))?;
+      assert_eq!(None, taken);
+      assert!(board.at((7, 7)).is_none());
+      assert_eq!(PAWN, board.at((7, 6)).unwrap().piece.piece);
+      Ok(())
+   }
+
+   #[test]
+   fn test_bishop_exchange() -> ErrStr<()> {
+      let mut board = Board::default();
+      board.apply(&mk_move(1, PAWN, Some(pos(7, "g")), pos(7, "f"), false))?;
+      board.apply(&mk_move(2, PAWN, Some(pos(3, "c")), pos(3, "d"), false))?;
+      let mut bx = mk_move(3, BISHOP, Some(pos(8, "h")), pos(2, "b"), true);
+      bx.promote = Some(true);
+      let taken = board.apply(&bx)?;
+      assert_eq!(Some(Piece { promoted: false, piece: BISHOP }), taken);
+      assert_eq!(1, board.in_hand(&BLACK, BISHOP));
+      assert!(board.at((2, 2)).unwrap().piece.promoted);
+      Ok(())
+   }
+
+   #[test]
+   fn test_off_the_board() {
+      let board = Board::default();
+      assert!(board.at((0, 5)).is_none());
+      assert!(board.at((10, 10)).is_none());
+   }
+
+   #[test]
+   fn fail_blocked_bishop() {
+      let mut board = Board::default();
+      let bx = mk_move(1, BISHOP, Some(pos(8, "h")), pos(2, "b"), true);
+      assert!(board.apply(&bx).is_err());
+   }
+
+   #[test]
+   fn fail_drop_from_empty_hand() {
+      let mut board = Board::default();
+      assert!(board.apply(&mk_move(1, GOLD, None, pos(5, "e"), false)).is_err());
+   }
+
+   #[test]
+   fn test_hash_of_the_position() -> ErrStr<()> {
+      let mut board = Board::default();
+      let start = board.hash();
+      board.apply(&mk_move(1, ROOK, Some(pos(2, "h")), pos(3, "h"), false))?;
+      assert_ne!(start, board.hash());
+      board.apply(&mk_move(2, ROOK, Some(pos(8, "b")), pos(7, "b"), false))?;
+      board.apply(&mk_move(3, ROOK, Some(pos(3, "h")), pos(2, "h"), false))?;
+      board.apply(&mk_move(4, ROOK, Some(pos(7, "b")), pos(8, "b"), false))?;
+      assert_eq!(start, board.hash());
+      board.turn = WHITE;
+      assert_ne!(start, board.hash());
+      Ok(())
+   }
+
+   #[test]
+   fn test_in_check() {
+      let mut board = Board::empty();
+      board.put((5, 1), WHITE, KING);
+      board.put((1, 1), BLACK, ROOK);
+      assert!(board.in_check(&WHITE));
+      board.put((3, 1), WHITE, GOLD);
+      assert!(!board.in_check(&WHITE));
+      assert!(!board.in_check(&BLACK));
+   }
+
+   #[test]
+   fn test_legal_moves() -> ErrStr<()> {
+      assert_eq!(30, Board::default().legal_moves().len());
+      // checked along rank i, the king must leave it
+      let board = from_sfen("4k4/9/9/9/9/9/9/9/3K1r3 b - 1")?.board;
+      let moves: Vec<String> = board.legal_moves().iter().map(|m| m.to.to_string()).collect();
+      assert_eq!(vec!["7h", "5h", "6h"], moves);
+      Ok(())
+   }
+
+   #[test]
+   fn test_mate() -> ErrStr<()> {
+      // a gold on the king's head, backed by a pawn
+      assert!(from_sfen("4k4/4G4/4P4/9/9/9/9/9/9 w - 1")?.board.is_mate());
+      assert!(!from_sfen("4k4/4G4/9/9/9/9/9/9/9 w - 1")?.board.is_mate());
+      Ok(())
+   }
+
+   #[test]
+   fn test_pawn_drop_mate() -> ErrStr<()> {
+      // the king in the corner, hemmed in by its own knight and lance
+      let hemmed = "7nk/7l1/8G/9/9/9/9/9/9 b";
+      let pawn = from_sfen(&format!("{hemmed} P 1"))?.board;
+      assert!(pawn.legal_moves().iter().all(|m| !(m.drop && m.to == pos(1, "b"))));
+      let mut gold = from_sfen(&format!("{hemmed} G 1"))?.board;
+      let drop = gold.legal_moves().into_iter().find(|m| m.drop && m.to == pos(1, "b"))
+                     .ok_or("No G*1b")?;
+      gold.apply(&drop)?;
+      assert!(gold.is_mate());
+      let mut pawn = pawn;
+      let piece = Piece { promoted: false, piece: PAWN };
+      let err = pawn.apply(&Move { n: 1, piece, ..drop }).unwrap_err();
+      assert_eq!("Move 1: a pawn dropped may not mate", err);
+      Ok(())
+   }
+
+   #[test]
+   fn fail_against_the_rules() -> ErrStr<()> {
+      let refused = |sfen: &str, mov: Move| -> ErrStr<String> {
+         let mut board = from_sfen(sfen)?.board;
+         let before = board.hash();
+         let err = board.apply(&mov).unwrap_err();
+         assert_eq!(before, board.hash());
+         Ok(err)
+      };
+      let nifu = mk_move(1, PAWN, None, pos(5, "e"), false);
+      assert_eq!("Move 1: a second pawn on file 5 (nifu)",
+                 refused("4k4/9/9/9/9/9/4P4/9/4K4 b P 1", nifu)?);
+      let knight = mk_move(1, KNIGHT, None, pos(3, "b"), false);
+      assert_eq!("Move 1: KNIGHT dropped on 3b can never move",
+                 refused("4k4/9/9/9/9/9/9/9/4K4 b N 1", knight)?);
+      let early = Move { promote: Some(true), ..mk_move(1, SILVER, Some(pos(5, "f")),
+                                                         pos(5, "e"), false) };
+      assert_eq!("Move 1: SILVER cannot promote moving from 5f to 5e",
+                 refused("4k4/9/9/9/9/4S4/9/9/4K4 b - 1", early)?);
+      let stuck = mk_move(1, LANCE, Some(pos(1, "c")), pos(1, "a"), false);
+      assert_eq!("Move 1: LANCE on 1a can never move unpromoted",
+                 refused("4k4/9/8L/9/9/9/9/9/4K4 b - 1", stuck)?);
+      let pinned = mk_move(1, GOLD, Some(pos(5, "h")), pos(4, "h"), false);
+      assert_eq!("Move 1: leaves the mover's king in check",
+                 refused("8k/4r4/9/9/9/9/9/4G4/4K4 b - 1", pinned)?);
+      Ok(())
+   }
+}
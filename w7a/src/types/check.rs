@@ -0,0 +1,152 @@
+use book::err_utils::ErrStr;
+
+use super::{
+   board::Board,
+   cst::{Cst, TokenKind::NOTATION},
+   error::{ErrorKind::{ANNOTATION, OUTCOME}, W7AErr},
+   setup::setup,
+   w7a::{Ending::MATE, W7A}
+};
+
+// The replay marks each move that gives check; the last move of the record,
+// if it leaves the side to move no way out of check, mates. w7a writes the
+// mark after the move, as the annotators do:
+
+// 84.B5ex3cch      a check
+// 85.+R3bx3c#       mate
+
+// The reader takes a mark as the record's claim, and validate checks it.
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum Check { CHECK, CHECKMATE }
+
+impl Check {
+   /// The mark w7a writes after the move
+   pub fn suffix(&self) -> &'static str {
+      match self { Check::CHECK => "ch", Check::CHECKMATE => "#" }
+   }
+
+   /// The mark KIF (and JKF) comments have
+   pub fn kif(&self) -> &'static str {
+      match self { Check::CHECK => "王手", Check::CHECKMATE => "詰み" }
+   }
+}
+
+/// The notation without its mark, and the check the mark claims, if any.
+/// ("ch" cannot be mistaken for a square: a rank follows a file, a digit.)
+pub fn marked(notation: &str) -> (&str, Option<Check>) {
+   if let Some(rest) = notation.strip_suffix('#') {
+      (rest, Some(Check::CHECKMATE))
+   } else if let Some(rest) = notation.strip_suffix("ch") {
+      (rest, Some(Check::CHECK))
+   } else {
+      (notation, None)
+   }
+}
+
+/// The check the move just played on the board gives, if any; mate is
+/// looked for after the last move only
+pub fn gives(board: &Board, last: bool) -> Option<Check> {
+   if !board.in_check(&board.turn) {
+      None
+   } else if last && board.is_mate() {
+      Some(Check::CHECKMATE)
+   } else {
+      Some(Check::CHECK)
+   }
+}
+
+/// Each move's check, if it gives one, by its number
+pub fn checks(game: &W7A) -> ErrStr<Vec<(usize, Option<Check>)>> {
+   let mut board = setup(&game.header)?.board;
+   let last = game.moves.last().map(|m| m.n);
+   game.moves.iter().map(|mov| {
+      board.apply(mov)?;
+      Ok((mov.n, gives(&board, Some(mov.n) == last)))
+   }).collect()
+}
+
+/// Errors where the record marks a check, or mate, the board does not
+/// give, or ends in Mate when the side to move has a way out
+pub fn lint_checks(cst: &Cst, game: &W7A) -> Vec<W7AErr> {
+   // an illegal replay is found, and reported, by the conversion
+   let Ok(found) = checks(game) else { return Vec::new() };
+   let mut errs = Vec::new();
+   for (n, check) in &found {
+      let Some(notation) = cst.move_line(*n).and_then(|line| line.token(NOTATION)) else {
+         continue
+      };
+      let Some(claim) = marked(cst.text(notation.span)).1 else { continue };
+      let msg = match (claim, check) {
+         (_, None) => format!("Move {n} is marked {}, but gives no check", word(claim)),
+         (Check::CHECKMATE, Some(Check::CHECK)) =>
+            format!("Move {n} is marked mate, but the king has a way out"),
+         _ => continue
+      };
+      errs.push(W7AErr::new(ANNOTATION, &msg).at(cst, notation.span).on_move(*n));
+   }
+   if game.outcome.as_ref().is_some_and(|o| o.ending == MATE) {
+      let why = match found.last().and_then(|(_, check)| *check) {
+         Some(Check::CHECKMATE) => return errs,
+         Some(Check::CHECK) => "the king has a way out",
+         None => "the side to move is not in check"
+      };
+      errs.push(W7AErr::new(OUTCOME, &format!("Mate, but {why}")).on_result(cst));
+   }
+   errs
+}
+
+fn word(check: Check) -> &'static str {
+   match check { Check::CHECK => "check", Check::CHECKMATE => "mate" }
+}
+
+// ----- TESTS -------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   // White's king, on 5a, mated by a gold dropped on its head, the pawn on
+   // 5c backing it
+   const MATING: &str = "[SFEN \"4k4/9/4P4/9/9/9/9/9/9 b G 1\"]\n";
+
+   fn lint(source: &str) -> ErrStr<Vec<(Option<usize>, String)>> {
+      let cst = Cst::parse(source);
+      let game = W7A::from_cst(&cst)?;
+      Ok(lint_checks(&cst, &game).into_iter().map(|e| (e.line(), e.message)).collect())
+   }
+
+   #[test]
+   fn test_marked() {
+      assert_eq!(("B5ex3c", Some(Check::CHECK)), marked("B5ex3cch"));
+      assert_eq!(("+R3bx3c", Some(Check::CHECKMATE)), marked("+R3bx3c#"));
+      assert_eq!(("S4c-3h", None), marked("S4c-3h"));
+   }
+
+   #[test]
+   fn test_checks() -> ErrStr<()> {
+      let game: W7A = format!("{MATING}1.G*5b       00:00:00  00:00:00\n").parse()?;
+      assert_eq!(vec![(1, Some(Check::CHECKMATE))], checks(&game)?);
+      let game: W7A = format!("{MATING}1.G*4b       00:00:00  00:00:00\n\
+                               2.K5a-6a     00:00:00  00:00:00\n").parse()?;
+      assert_eq!(vec![(1, Some(Check::CHECK)), (2, None)], checks(&game)?);
+      Ok(())
+   }
+
+   #[test]
+   fn test_marks_checked() -> ErrStr<()> {
+      let mate = format!("{MATING}1.G*5b#      00:00:00  00:00:00\n\
+                          Mate         00:00:00  00:00:00\n");
+      assert!(lint(&mate)?.is_empty());
+      assert!(lint(&mate.replace("5b#", "5bch"))?.is_empty());
+      let wrong = format!("{MATING}1.G*4b#      00:00:00  00:00:00\n\
+                           Mate         00:00:00  00:00:00\n");
+      assert_eq!(vec![(Some(2), "Move 1 is marked mate, but the king has a way out".to_string()),
+                      (Some(3), "Mate, but the king has a way out".to_string())],
+                 lint(&wrong)?);
+      let quiet = format!("{MATING}1.G*6cch     00:00:00  00:00:00\n");
+      assert_eq!(vec![(Some(2), "Move 1 is marked check, but gives no check".to_string())],
+                 lint(&quiet)?);
+      Ok(())
+   }
+}
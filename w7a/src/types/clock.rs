@@ -0,0 +1,372 @@
+use std::{
+   fmt,
+   str::FromStr
+};
+
+use chrono::Duration;
+
+use book::err_utils::ErrStr;
+
+use super::{
+   board::opponent,
+   cst::{Cst, Line},
+   error::{ErrorKind::*, W7AErr},
+   setup::setup,
+   w7a::{Color::{self, *}, Ending::TIMEUP, W7A, color, dur},
+   writer::clock
+};
+
+// The clocks of a move line are cumulative, Black's then White's:
+
+// 81.B6d-5e    07:53:00  07:54:00
+// 82.S6ex5f    07:53:00  07:58:00
+
+// so a move may only advance the clock of the side moving. The result line
+// has no move to match a change, bar the clock of the side losing on time; the
+// 54th Oi-sen's first game has one, on White's clock:
+
+// Resigns      07:55:00  07:59:00
+
+// The lints warn of a clock going back, of the other side's clock changing,
+// of a think too long to be believed, and of totals over the time control.
+
+// Under a time control, a side's total is the main time it has used; a move
+// played once that is spent (or one that spends it) is played in byoyomi:
+
+// [TimeControl "8h+1m"]    持ち時間：各8時間（秒読み1分）
+
+/// A think longer than this, in seconds, is more likely a typo
+pub const LONGEST_THINK: i64 = 3 * 60 * 60;
+
+/// The time control of a [TimeControl "8h+1m"] header: the main time, then
+/// the byoyomi, if any, that each move may take after it
+#[derive(Debug,Clone,PartialEq)]
+pub struct TimeControl {
+   pub main: Duration,
+   pub byoyomi: Option<Duration>
+}
+
+impl FromStr for TimeControl {
+   type Err = String;
+   fn from_str(s: &str) -> ErrStr<Self> {
+      let (main, byoyomi) = match s.split_once('+') {
+         Some((main, byoyomi)) => (main, Some(byoyomi)),
+         None => (s, None)
+      };
+      Ok(TimeControl { main: amount(main, s)?,
+                       byoyomi: byoyomi.map(|b| amount(b, s)).transpose()? })
+   }
+}
+
+impl TimeControl {
+   /// The main time left to a side that has used total
+   pub fn remaining(&self, total: Duration) -> Duration {
+      (self.main - total).max(Duration::zero())
+   }
+
+   /// Whether a move, taking its side's total from before to after, is
+   /// played in byoyomi
+   pub fn in_byoyomi(&self, before: Duration, after: Duration) -> bool {
+      self.byoyomi.is_some() && (before >= self.main || after > self.main)
+   }
+
+   /// The time control as KIF's 持ち時間 has it: 各8時間（秒読み1分）
+   pub fn kif(&self) -> String {
+      let byoyomi = self.byoyomi.map_or(String::new(), |b| {
+         format!("（秒読み{}）", words(b, ["時間", "分", "秒"]))
+      });
+      format!("各{}{byoyomi}", words(self.main, ["時間", "分", "秒"]))
+   }
+
+   /// The time control of KIF's 持ち時間, if it is written as kif() writes it
+   pub fn from_kif(text: &str) -> Option<Self> {
+      let text = text.trim().trim_start_matches('各');
+      let (main, rest) = text.split_once('（').unwrap_or((text, ""));
+      let byoyomi = rest.strip_prefix("秒読み").map(|b| b.trim_end_matches('）'));
+      let control = match byoyomi {
+         Some(byoyomi) => format!("{main}+{byoyomi}"),
+         None if rest.is_empty() => main.to_string(),
+         None => return None
+      };
+      control.replace("時間", "h").replace('分', "m").replace('秒', "s")
+             .parse().ok()
+   }
+}
+
+// the header value: 8h+1m
+impl fmt::Display for TimeControl {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      write!(f, "{}", words(self.main, ["h", "m", "s"]))?;
+      match self.byoyomi {
+         Some(byoyomi) => write!(f, "+{}", words(byoyomi, ["h", "m", "s"])),
+         None => Ok(())
+      }
+   }
+}
+
+// e.g. 8h30m, in the units given for hours, minutes and seconds
+fn words(d: Duration, units: [&str; 3]) -> String {
+   let secs = d.num_seconds();
+   let parts = [secs / 3600, (secs / 60) % 60, secs % 60];
+   let words: String = parts.iter()
+                            .zip(units)
+                            .filter(|(n, _)| **n > 0)
+                            .map(|(n, unit)| format!("{n}{unit}"))
+                            .collect();
+   if words.is_empty() { format!("0{}", units[2]) } else { words }
+}
+
+/// The time control of the game's TimeControl header, if it has one
+pub fn time_control(game: &W7A) -> ErrStr<Option<TimeControl>> {
+   game.tag("TimeControl").map(str::parse).transpose()
+}
+
+/// A move's time: how long its side thought, its side's total, the main time
+/// it has left, under a time control, and whether it is played in byoyomi
+#[derive(Debug,Clone,PartialEq)]
+pub struct Think {
+   pub n: usize,
+   pub color: Color,
+   pub think: Duration,
+   pub total: Duration,
+   pub remaining: Option<Duration>,
+   pub byoyomi: bool
+}
+
+/// The thinks of the game's moves, under the time control, if any
+pub fn thinks(game: &W7A, control: Option<&TimeControl>) -> Vec<Think> {
+   game.moves.iter().enumerate().map(|(i, mov)| {
+      let last_own = i.checked_sub(2).and_then(|j| game.moves.get(j));
+      let before = last_own.map_or(Duration::zero(), |m| m.total_time);
+      Think { n: mov.n, color: color(mov), think: dur(last_own, mov),
+              total: mov.total_time,
+              remaining: control.map(|c| c.remaining(mov.total_time)),
+              byoyomi: control.is_some_and(|c| c.in_byoyomi(before, mov.total_time)) }
+   }).collect()
+}
+
+/// The move on which the side entered byoyomi, if it did
+pub fn byoyomi_from(thinks: &[Think], side: &Color) -> Option<usize> {
+   thinks.iter().find(|t| t.color == *side && t.byoyomi).map(|t| t.n)
+}
+
+// e.g. "8h", "1m", "8h30m", "30s"
+fn amount(text: &str, control: &str) -> ErrStr<Duration> {
+   let bad = || format!("Time control {control} is not of the form 8h+1m");
+   let text = text.trim();
+   if text.is_empty() { return Err(bad()); }
+   let mut total = Duration::zero();
+   let mut digits = String::new();
+   for c in text.chars() {
+      match c {
+         '0'..='9' => digits.push(c),
+         'h' | 'm' | 's' if !digits.is_empty() => {
+            let unit = match c { 'h' => 3600, 'm' => 60, _ => 1 };
+            let secs = digits.parse::<i64>().ok()
+                             .and_then(|n| n.checked_mul(unit))
+                             .and_then(Duration::try_seconds)
+                             .ok_or_else(bad)?;
+            total += secs;
+            digits.clear();
+         },
+         _ => return Err(bad())
+      }
+   }
+   if digits.is_empty() { Ok(total) } else { Err(bad()) }
+}
+
+// a clock line: who, if anyone, may use time on it, and the clocks it shows
+struct Reading<'a> {
+   line: &'a Line,
+   n: Option<usize>,
+   mover: Option<Color>,
+   clocks: [Duration; 2]
+}
+
+/// Warnings of the clocks that cannot be right
+pub fn lint_clocks(cst: &Cst, game: &W7A) -> Vec<W7AErr> {
+   let mut readings: Vec<Reading> = game.moves.iter().filter_map(|mov| {
+      let line = cst.move_line(mov.n)?;
+      let clocks = if color(mov) == BLACK { [mov.total_time, mov.other_time] }
+                   else { [mov.other_time, mov.total_time] };
+      Some(Reading { line, n: Some(mov.n), mover: Some(color(mov)), clocks })
+   }).collect();
+   let result = cst.result_line();
+   if let (Some(outcome), Some(line)) = (&game.outcome, result) {
+      let to_move = match game.moves.last() {
+         Some(mov) => opponent(&mov.color),
+         None => setup(&game.header).map_or(BLACK, |setup| setup.board.turn)
+      };
+      let mover = (outcome.ending == TIMEUP).then_some(to_move);
+      readings.push(Reading { line, n: None, mover,
+                              clocks: [outcome.black_time, outcome.white_time] });
+   }
+   // a line without both clocks says nothing of them
+   readings.retain(|reading| reading.line.clocks().len() == 2);
+   let mut found = Vec::new();
+   let mut before = [Duration::zero(), Duration::zero()];
+   for reading in &readings {
+      found.extend(lint_reading(cst, reading, before));
+      before = reading.clocks;
+   }
+   match (time_control(game), readings.last()) {
+      (Ok(Some(control)), Some(last)) => {
+         found.extend(over_time(cst, game, &control, last));
+      },
+      (Err(msg), _) => found.push(W7AErr::new(HEADER, &msg).warning()
+                                          .on_tag("TimeControl")
+                                          .locate(cst)),
+      _ => ()
+   }
+   found
+}
+
+fn side(index: usize) -> &'static str {
+   if index == 0 { "Black" } else { "White" }
+}
+
+fn lint_reading(cst: &Cst, reading: &Reading, before: [Duration; 2])
+      -> Vec<W7AErr> {
+   let spans = reading.line.clocks();
+   let moving = reading.mover.as_ref().map(|c| if *c == BLACK { 0 } else { 1 });
+   let mut found = Vec::new();
+   for (i, (was, now)) in before.iter().zip(reading.clocks).enumerate() {
+      let msg = if now < *was {
+         format!("{}'s clock goes back, from {} to {}",
+                 side(i), clock(*was), clock(now))
+      } else if moving != Some(i) && now != *was {
+         let on = match moving {
+            Some(moving) => format!("on {}'s move", side(moving)),
+            None => "with no move to match".to_string()
+         };
+         format!("{}'s clock changes, from {} to {}, {on}",
+                 side(i), clock(*was), clock(now))
+      } else if now - *was > Duration::seconds(LONGEST_THINK) {
+         format!("{} thinks for {} on one move", side(i), clock(now - *was))
+      } else {
+         continue
+      };
+      let warning = W7AErr::new(BADCLOCK, &msg).warning().at(cst, spans[i]);
+      found.push(match reading.n { Some(n) => warning.on_move(n), None => warning });
+   }
+   found
+}
+
+// each side's total is at most the main time, and a byoyomi per move; one
+// losing on time may go over
+fn over_time(cst: &Cst, game: &W7A, control: &TimeControl, last: &Reading)
+      -> Vec<W7AErr> {
+   let spans = last.line.clocks();
+   let timeout = game.outcome.as_ref().is_some_and(|o| o.ending == TIMEUP);
+   let mut found = Vec::new();
+   for (i, total) in last.clocks.iter().enumerate() {
+      let moving = if i == 0 { BLACK } else { WHITE };
+      if timeout && last.n.is_none() && last.mover.as_ref() == Some(&moving) {
+         continue;
+      }
+      let moves = game.moves.iter().filter(|m| color(m) == moving).count() as i32;
+      let limit = control.main
+                     + control.byoyomi.map_or(Duration::zero(), |b| b * moves);
+      if *total > limit {
+         let msg = format!("{}'s clock, {}, is over the time control's {}",
+                           side(i), clock(*total), clock(limit));
+         found.push(W7AErr::new(BADCLOCK, &msg).warning().at(cst, spans[i]));
+      }
+   }
+   found
+}
+
+// ----- TESTS -------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   use crate::types::w7a::load_cst;
+
+   fn messages(source: &str) -> Vec<(Option<usize>, String)> {
+      let cst = Cst::parse(source);
+      let (game, _) = W7A::recover(&cst);
+      lint_clocks(&cst, &game).into_iter()
+                              .map(|err| (err.line(), err.message))
+                              .collect()
+   }
+
+   #[test]
+   fn test_time_control() {
+      let title: ErrStr<TimeControl> = "8h+1m".parse();
+      assert_eq!(Ok(TimeControl { main: Duration::hours(8),
+                                  byoyomi: Some(Duration::minutes(1)) }), title);
+      let rapid: ErrStr<TimeControl> = "1h30m".parse();
+      assert_eq!(Ok(Duration::minutes(90)), rapid.map(|tc| tc.main));
+      assert!("eight hours".parse::<TimeControl>().is_err());
+      assert!("8h+".parse::<TimeControl>().is_err());
+   }
+
+   #[test]
+   fn test_byoyomi() {
+      let control: TimeControl = TimeControl { main: Duration::minutes(10),
+                                               byoyomi: Some(Duration::seconds(30)) };
+      assert_eq!("10m+30s", control.to_string());
+      assert_eq!("各10分（秒読み30秒）", control.kif());
+      assert_eq!(Some(control.clone()), TimeControl::from_kif(&control.kif()));
+      assert_eq!(None, TimeControl::from_kif("各8時間（チェスクロック使用）"));
+      let cst = Cst::parse("1.P7g-7f     00:06:00  00:00:00\n\
+                            2.P3c-3d     00:06:00  00:09:50\n\
+                            3.P2g-2f     00:10:20  00:09:50\n\
+                            4.P8c-8d     00:10:20  00:10:00\n\
+                            5.P2f-2e     00:10:40  00:10:00\n");
+      let (game, _) = W7A::recover(&cst);
+      let thinks = thinks(&game, Some(&control));
+      let byoyomi: Vec<bool> = thinks.iter().map(|t| t.byoyomi).collect();
+      assert_eq!(vec![false, false, true, false, true], byoyomi);
+      assert_eq!(Some(Duration::seconds(10)), thinks[1].remaining);
+      assert_eq!(Duration::seconds(260), thinks[2].think);
+      assert_eq!((Some(3), None), (byoyomi_from(&thinks, &BLACK),
+                                   byoyomi_from(&thinks, &WHITE)));
+   }
+
+   #[test]
+   fn test_oi_clocks() -> ErrStr<()> {
+      let dir = "../data/game_records/reijer_grimberger";
+      let cst = load_cst(&format!("{dir}/2013-07-11-54th-oi-sen-game-1.w7a"))?;
+      let (game, _) = W7A::recover(&cst);
+      let found = lint_clocks(&cst, &game);
+      assert_eq!(1, found.len());
+      assert_eq!((Some(165), Some(24)), (found[0].line(), found[0].column()));
+      assert_eq!("White's clock changes, from 07:58:00 to 07:59:00, with no \
+                  move to match", found[0].message);
+      Ok(())
+   }
+
+   #[test]
+   fn test_clock_typos() {
+      let found = messages("1.P7g-7f     00:05:00  00:00:00\n\
+                            2.P3c-3d     00:04:00  00:01:00\n\
+                            3.P2g-2f     00:06:00  00:02:00\n\
+                            4.P8c-8d     00:06:00  03:30:00\n\
+                            Resigns      00:07:00  03:30:00\n");
+      assert_eq!(vec![
+         (Some(2), "Black's clock goes back, from 00:05:00 to 00:04:00".to_string()),
+         (Some(3), "White's clock changes, from 00:01:00 to 00:02:00, on \
+                    Black's move".to_string()),
+         (Some(4), "White thinks for 03:28:00 on one move".to_string()),
+         (Some(5), "Black's clock changes, from 00:06:00 to 00:07:00, with no \
+                    move to match".to_string())
+      ], found);
+   }
+
+   #[test]
+   fn test_over_time() {
+      let found = messages("[TimeControl \"10m+30s\"]\n\
+                            1.P7g-7f     00:09:00  00:00:00\n\
+                            2.P3c-3d     00:09:00  00:10:40\n\
+                            Timeout      00:10:20  00:10:40\n");
+      assert_eq!(vec![(Some(4), "White's clock, 00:10:40, is over the time \
+                                 control's 00:10:30".to_string())], found);
+      let found = messages("[TimeControl \"8 hours\"]\n\
+                            1.P7g-7f     00:09:00  00:00:00\n");
+      assert_eq!(vec![(Some(1), "Time control 8 hours is not of the form \
+                                 8h+1m".to_string())], found);
+   }
+}
@@ -3,7 +3,12 @@ use std::{
    fmt
 };
 
-use chrono::Month;
+use chrono::{Datelike, Duration, Month, NaiveDate};
+
+use shogi_kifu_converter::jkf::{
+   Color as JColor, Hand, Kind, MoveFormat, MoveMoveFormat, MoveSpecial,
+   Piece as JPiece, PlaceFormat, Preset, StateFormat, Time, TimeFormat
+};
 
 use book::{
    err_utils::{ErrStr,err_or},
@@ -11,15 +16,28 @@ use book::{
 };
 
 use super::{
-   jkf::{Header as JHdr, mk_jhdr, Initial},
-   w7a::Header as Hdr,
+   board::{Board, square},
+   check::gives,
+   setup::{Setup, setup},
+   clock::{TimeControl, time_control},
+   error::{ErrW7A, ErrorKind::*, W7AErr},
+   relative::relative,
+   session::last_day,
+   jkf::{
+      mk_jhdr, Initial, mk_init, mk_position, JKF, mk_jkf,
+      Moves, mk_moves
+   },
+   w7a::{
+      Header as Hdr, W7A, Move, Outcome, Ending, Ending::*, Color, Color::*,
+      Piece, PieceType::*, Position, color, dur
+   },
    utils::Lookup
 };
 
 /// Converts from w7a-types to JKF
 
 pub trait Convert<W, J: AsJSON> { 
-   fn convert(&self, domain: &W) -> ErrStr<J>; 
+   fn convert(&self, domain: &W) -> ErrW7A<J>; 
 }
 
 type XformJ = dyn Fn(String) -> ErrStr<JsonString>;
@@ -35,6 +53,9 @@ impl<'a> Default for Converter<'a> {
 
 fn to_j_str(s: String) -> ErrStr<JsonString> { Ok(mk_jstr(&s)) }
 fn to_j_dt(s: String) -> ErrStr<JsonString> { convert_date(&s) }
+fn to_j_tc(s: String) -> ErrStr<JsonString> {
+   Ok(mk_jstr(&s.parse::<TimeControl>()?.kif()))
+}
 
 fn populate<'a>(raw: &[((&str, &str), &'a XformJ)]) -> Transform<'a> {
    fn strify((a, b): &(&str, &str)) -> (String, String) {
@@ -46,34 +67,224 @@ fn populate<'a>(raw: &[((&str, &str), &'a XformJ)]) -> Transform<'a> {
 }
 
 fn headers<'a>() -> Transform<'a> {
-   populate(&[(("Black", "後手"), &to_j_str), (("White", "先手"), &to_j_str),
-              (("Event", "棋戦"), &to_j_str), (("Date", "開始日時"), &to_j_dt)])
+   populate(&[(("Black", "先手"), &to_j_str), (("White", "後手"), &to_j_str),
+              (("Event", "棋戦"), &to_j_str), (("Date", "開始日時"), &to_j_dt),
+              (("TimeControl", "持ち時間"), &to_j_tc)])
 }
 
 // The Predule encapsulates the header- and initial-sections of the JKF
 struct Prelude {
-   header: JHdr,
+   header: Lookup,
    initial: Initial
 }
 
 impl AsJSON for Prelude {
    fn as_json(&self) -> String {
-      format!("{},\n{}", self.header.as_json(), self.initial.as_json())
+      format!("{},\n{}", mk_jhdr(self.header.clone()).as_json(), self.initial.as_json())
    }
 }
 
 impl<'a> Convert<Hdr, Prelude> for Converter<'a> {
-   fn convert(&self, domain: &Hdr) -> ErrStr<Prelude> {
+   fn convert(&self, domain: &Hdr) -> ErrW7A<Prelude> {
       let mut hdr: Lookup = HashMap::new();
       for (key,f) in &self.header {
          let (k,v) = key;
          if let Some(raw_val) = domain.header.get(k) {
-            let ans = f(raw_val.to_string())?.as_json();
+            let ans = f(raw_val.to_string()).map_err(|e| {
+               let kind = if k == "Date" { BADDATE } else { HEADER };
+               W7AErr::new(kind, &e).on_tag(k)
+            })?.as_json();
             hdr.insert(v.clone(), ans);
          }
       }
-      Ok(Prelude { header: mk_jhdr(hdr), initial: Initial::default() })
+      let setup = game_setup(domain)?;
+      let initial = if setup.preset == Preset::PresetOther {
+         mk_position(state(&setup.board))
+      } else {
+         mk_init(setup.preset)
+      };
+      Ok(Prelude { header: hdr, initial })
+   }
+}
+
+fn game_setup(header: &Hdr) -> ErrW7A<Setup> {
+   setup(header).map_err(|e| {
+      let tag = if header.header.contains_key("SFEN") { "SFEN" } else { "Handicap" };
+      W7AErr::new(HEADER, &e).on_tag(tag)
+   })
+}
+
+// the position of an SFEN, for JKF: the board, board[file - 1][rank - 1],
+// the side to move, and the hands
+fn state(board: &Board) -> StateFormat {
+   let mut squares = [[JPiece::default(); 9]; 9];
+   for ((x, y), occ) in board.occupied() {
+      squares[x - 1][y - 1] =
+         JPiece { color: Some(jcolor(&occ.color)), kind: Some(kind(&occ.piece)) };
+   }
+   let hand = |c: &Color| {
+      let n = |piece| board.in_hand(c, piece) as u8;
+      Hand { FU: n(PAWN), KY: n(LANCE), KE: n(KNIGHT), GI: n(SILVER), KI: n(GOLD),
+             KA: n(BISHOP), HI: n(ROOK) }
+   };
+   StateFormat { color: jcolor(&board.turn), board: squares,
+                 hands: [hand(&BLACK), hand(&WHITE)] }
+}
+
+fn jcolor(c: &Color) -> JColor {
+   if *c == BLACK { JColor::Black } else { JColor::White }
+}
+
+impl<'a> Convert<W7A, JKF> for Converter<'a> {
+   fn convert(&self, domain: &W7A) -> ErrW7A<JKF> {
+      let Prelude { mut header, initial } = self.convert(&domain.header)?;
+      // a game played over days ends on the day of its last session
+      if let Some(day) = last_day(domain) {
+         header.insert("終了日時".to_string(), day.format("%Y/%m/%d").to_string());
+      }
+      let moves = convert_moves(domain)?;
+      Ok(mk_jkf(mk_jhdr(header), initial, moves))
+   }
+}
+
+// ---- MOVES ------------------------------------------------------------
+
+// Replays the game on the board, from its setup, because JKF wants to know
+// what was captured.
+// A move that gives check is marked 王手, or, ending the game in mate, 詰み;
+// under a time control with byoyomi, the moves played in it are marked 秒読み,
+// before that; a sealed move is marked 封じ手, before that.
+fn convert_moves(game: &W7A) -> ErrW7A<Moves> {
+   let control = time_control(game).ok().flatten();
+   let mut board = game_setup(&game.header)?.board;
+   let mut moves = vec![comments(&game.game_comment.comment)];
+   let mut prev: Option<&Move> = None;
+   for (i, mov) in game.moves.iter().enumerate() {
+      let illegal = |e: String| W7AErr::new(ILLEGAL, &e).on_move(mov.n);
+      let rel = relative(&board, mov).map_err(illegal)?;
+      let captured = board.apply(mov).map_err(illegal)?;
+      let check = gives(&board, i + 1 == game.moves.len());
+      let last_own = if i >= 2 { game.moves.get(i - 2) } else { None };
+      let same = prev.is_some_and(|p| p.to == mov.to);
+      let mut jmov = comments(&mov.comment);
+      if let Some(check) = check {
+         jmov.comments.get_or_insert_with(Vec::new).insert(0, check.kif().to_string());
+      }
+      let before = last_own.map_or(Duration::zero(), |m| m.total_time);
+      if control.as_ref().is_some_and(|c| c.in_byoyomi(before, mov.total_time)) {
+         jmov.comments.get_or_insert_with(Vec::new).insert(0, "秒読み".to_string());
+      }
+      if game.sealed.contains(&mov.n) {
+         jmov.comments.get_or_insert_with(Vec::new).insert(0, "封じ手".to_string());
+      }
+      let mut mmov = convert_move(mov, captured.as_ref(), same).map_err(illegal)?;
+      mmov.relative = rel;
+      jmov.move_ = Some(mmov);
+      let clock = |e: String| W7AErr::new(BADCLOCK, &e).on_move(mov.n);
+      jmov.time = Some(Time { now: now_format(dur(last_own, mov)).map_err(clock)?,
+                              total: time_format(mov.total_time).map_err(clock)? });
+      moves.push(jmov);
+      prev = Some(mov);
+   }
+   if let Some(outcome) = &game.outcome {
+      let n = game.moves.len();
+      let last_own = if n >= 2 { game.moves.get(n - 2) } else { None };
+      moves.push(convert_outcome(outcome, last_own, &board.turn)
+                    .map_err(|e| W7AErr::new(BADCLOCK, &e))?);
    }
+   Ok(mk_moves(moves))
+}
+
+fn convert_move(mov: &Move, captured: Option<&Piece>, same: bool)
+      -> ErrStr<MoveMoveFormat> {
+   let color = jcolor(&color(mov));
+   let from = mov.from.as_ref().map(place).transpose()?;
+   Ok(MoveMoveFormat {
+      color,
+      from,
+      to: place(&mov.to)?,
+      piece: kind(&mov.piece),
+      same: same.then_some(true),
+      promote: mov.promote,
+      capture: captured.map(kind),
+      relative: None
+   })
+}
+
+// The result is timed as the move of the side that, e.g., resigned
+fn convert_outcome(outcome: &Outcome, last_own: Option<&Move>, to_move: &Color)
+      -> ErrStr<MoveFormat> {
+   let mut jmov = comments(&outcome.comment);
+   jmov.special = Some(special(&outcome.ending));
+   let total = if *to_move == BLACK { outcome.black_time } else { outcome.white_time };
+   let start = last_own.map(|m| m.total_time).unwrap_or(Duration::zero());
+   jmov.time = Some(Time { now: now_format(total - start)?,
+                           total: time_format(total)? });
+   Ok(jmov)
+}
+
+// one JKF comment-line per paragraph, with a blank line between paragraphs
+fn comments(comment: &Option<String>) -> MoveFormat {
+   MoveFormat { comments: comment.as_ref()
+                                 .map(|c| c.lines().map(String::from).collect()),
+                ..MoveFormat::default() }
+}
+
+fn place(pos: &Position) -> ErrStr<PlaceFormat> {
+   let (x, y) = square(pos)?;
+   Ok(PlaceFormat { x: x as u8, y: y as u8 })
+}
+
+/// The JKF piece-kind, promoted pieces being kinds of their own
+pub fn kind(piece: &Piece) -> Kind {
+   match (piece.piece, piece.promoted) {
+      (PAWN, false) => Kind::FU,
+      (PAWN, true) => Kind::TO,
+      (LANCE, false) => Kind::KY,
+      (LANCE, true) => Kind::NY,
+      (KNIGHT, false) => Kind::KE,
+      (KNIGHT, true) => Kind::NK,
+      (SILVER, false) => Kind::GI,
+      (SILVER, true) => Kind::NG,
+      (GOLD, _) => Kind::KI,
+      (BISHOP, false) => Kind::KA,
+      (BISHOP, true) => Kind::UM,
+      (ROOK, false) => Kind::HI,
+      (ROOK, true) => Kind::RY,
+      (KING, _) => Kind::OU
+   }
+}
+
+fn special(ending: &Ending) -> MoveSpecial {
+   match ending {
+      RESIGNS => MoveSpecial::SpecialToryo,
+      SENNICHITE => MoveSpecial::SpecialSennichite,
+      JISHOGI => MoveSpecial::SpecialJishogi,
+      TIMEUP => MoveSpecial::SpecialTimeUp,
+      MATE => MoveSpecial::SpecialTsumi,
+      ABANDONED => MoveSpecial::SpecialChudan
+   }
+}
+
+// KIF writes the time of a move in minutes, (80:00/...), not hours and
+// minutes, so neither do we, unless the think is too long for that
+fn now_format(d: Duration) -> ErrStr<TimeFormat> {
+   let mins = d.num_minutes();
+   if mins > u8::MAX as i64 || mins < 0 {
+      time_format(d)
+   } else {
+      Ok(TimeFormat { h: None, m: mins as u8, s: (d.num_seconds() % 60) as u8 })
+   }
+}
+
+// JKF holds the hours in a byte: a clock past 255 hours is an error, not
+// one that wraps around to a few
+fn time_format(d: Duration) -> ErrStr<TimeFormat> {
+   let secs = d.num_seconds();
+   let h = u8::try_from(secs / 3600).ok().filter(|_| secs >= 0).ok_or_else(|| {
+      format!("A time of {secs} seconds is outside JKF's 0 to 255 hours")
+   })?;
+   Ok(TimeFormat { h: Some(h), m: ((secs / 60) % 60) as u8, s: (secs % 60) as u8 })
 }
 
 // ---- DATE-stuff -------------------------------------------------------
@@ -98,11 +309,11 @@ enum DateConverter { MONTH, DATE }
 use DateConverter::*;
 
 impl Convert<String, JsonString> for DateConverter {
-   fn convert(&self, s: &String) -> ErrStr<JsonString> {
+   fn convert(&self, s: &String) -> ErrW7A<JsonString> {
       match self {
          MONTH => convert_month(s),
          DATE => convert_date(s)
-      }
+      }.map_err(|e| W7AErr::new(BADDATE, &e))
    }
 }
 
@@ -115,24 +326,36 @@ fn convert_month(m: &str) -> ErrStr<JsonString> {
 // the Datish, as you see from the example, is broken up into
 // month [day-of-month information] year
 fn convert_date(dt: &str) -> ErrStr<JsonString> {
+   let (first, _) = date_range(dt)?;
+   let m1 = convert_month(dt.split(' ').next().unwrap_or_default())?;
+   Ok(mk_jstr(&format!("{}/{m1}/{} 00:00:01", first.year(), two_digits(first.day()))))
+}
+
+/// The first and the last days of the Date, e.g. July 10th and 11th 2013
+pub fn date_range(dt: &str) -> ErrStr<(NaiveDate, NaiveDate)> {
    let date_parts: Vec<&str> = dt.split(" ").collect();
    let mos = date_parts.first()
-                       .ok_or(format!("no month in empty string"))?;
-   let m1 = convert_month(&mos)?;
-   let day = date_parts.get(1)
-                       .ok_or(&format!("Could not fetch day from {dt}"))?;
-   let day_nums: String =
-            day.chars().filter(|c| c.is_ascii_digit()).collect();
-   let d1 = err_or(day_nums.parse(),
-                   &format!("Cannot parse day from {day_nums}"))?;
+                       .ok_or("no month in empty string".to_string())?;
+   let month: Month = err_or(mos.parse(), &format!("Can't parse month {mos}"))?;
    let year_str = date_parts.last()
                             .ok_or("Cannot scan year from empty string")?;
-   if year_str.chars().all(|c| c.is_ascii_digit()) {
-      let json = mk_jstr(&format!("{year_str}/{m1}/{} 00:00:01",
-                                  two_digits(d1)));
-      Ok(json)
-   } else {
-      Err(format!("Cannot parse the year {year_str}"))
+   if date_parts.len() < 3 || !year_str.chars().all(|c| c.is_ascii_digit()) {
+      return Err(format!("Cannot parse the year {year_str}"));
+   }
+   let year = err_or(year_str.parse(), &format!("Cannot parse the year {year_str}"))?;
+   // the words between that have digits are the days: 10th, 11th
+   let days: Vec<u32> =
+      date_parts[1..date_parts.len() - 1].iter().filter_map(|day| {
+         let day_nums: String = day.chars().filter(|c| c.is_ascii_digit()).collect();
+         day_nums.parse().ok()
+      }).collect();
+   let date = |d: &u32| {
+      NaiveDate::from_ymd_opt(year, month.number_from_month(), *d)
+                .ok_or(format!("Could not fetch day from {dt}"))
+   };
+   match (days.first(), days.last()) {
+      (Some(first), Some(last)) => Ok((date(first)?, date(last)?)),
+      _ => Err(format!("Could not fetch day from {dt}"))
    }
 }
 
@@ -145,9 +368,14 @@ mod tests {
 
    use super::*;
 
-   use crate::types::w7a::load_w7a_header;
+   use shogi_kifu_converter::jkf::Relative;
+
+   use crate::types::{
+      cst::Cst,
+      w7a::{load_w7a_header, load_w7a}
+   };
 
-   fn convert_month(m: &String) -> ErrStr<JsonString> {
+   fn convert_month(m: &String) -> ErrW7A<JsonString> {
       MONTH.convert(m)
    }
    
@@ -171,7 +399,7 @@ mod tests {
       fail_month("Lavinge");
    }
 
-   fn convert_date(dt: &String) -> ErrStr<JsonString> {
+   fn convert_date(dt: &String) -> ErrW7A<JsonString> {
       DATE.convert(dt)
    }
 
@@ -207,5 +435,113 @@ mod tests {
       // json.and_then(|j| { assert_eq!(" ", j.as_json()); Ok(())})
       Ok(())
    }
-}
 
+   // --- moves ---------------------------------------------------
+
+   fn oi_game() -> ErrStr<W7A> {
+      Ok(load_w7a("../data/game_records/reijer_grimberger/2013-07-11-54th-oi-sen-game-1.w7a")?)
+   }
+
+   #[test]
+   fn test_illegal_move_located() {
+      let cst = Cst::parse("[Black \"Habu\"]\n1.B8hx2b+    00:00:00  00:00:00\n");
+      let game = W7A::from_cst(&cst).expect("a well-formed game");
+      let err = Converter::default().convert(&game).map(|_| ()).unwrap_err();
+      let err = err.locate(&cst);
+      assert_eq!((ILLEGAL, Some(1)), (err.kind, err.n));
+      assert_eq!((Some(2), Some(3)), (err.line(), err.column()));
+   }
+
+   fn oi_moves() -> ErrStr<Vec<MoveFormat>> {
+      let game = oi_game()?;
+      let json = Converter::default().convert(&game)?.as_json();
+      let jkf: serde_json::Value = err_or(serde_json::from_str(&json),
+                                          "JKF is not JSON")?;
+      err_or(serde_json::from_value(jkf["moves"].clone()),
+             "moves are not JKF moves")
+   }
+
+   #[test]
+   fn test_convert_moves() -> ErrStr<()> {
+      let moves = oi_moves()?;
+      assert_eq!(85, moves.len()); // game comment, 83 moves, resignation
+      assert!(moves[0].move_.is_none());
+      assert_eq!(Some(MoveSpecial::SpecialToryo), moves[84].special);
+      Ok(())
+   }
+
+   #[test]
+   fn test_bishop_exchange_and_same() -> ErrStr<()> {
+      let moves = oi_moves()?;
+      let bx = moves[10].move_.ok_or("no move 10")?;
+      assert_eq!(Kind::KA, bx.piece);
+      assert_eq!(Some(Kind::KA), bx.capture);
+      assert_eq!(Some(true), bx.promote);
+      assert_eq!(JColor::White, bx.color);
+      let sx = moves[11].move_.ok_or("no move 11")?;
+      assert_eq!(Some(Kind::UM), sx.capture);
+      assert_eq!(Some(true), sx.same);
+      assert_eq!(Some(PlaceFormat { x: 8, y: 8 }), sx.from);
+      Ok(())
+   }
+
+   #[test]
+   fn test_relative_in_moves() -> ErrStr<()> {
+      let moves = oi_moves()?;
+      let gx = moves[67].move_.ok_or("no move 67")?;
+      assert_eq!(Some(Relative::R), gx.relative);
+      assert_eq!(None, moves[1].move_.ok_or("no move 1")?.relative);
+      Ok(())
+   }
+
+   #[test]
+   fn test_drop_and_time() -> ErrStr<()> {
+      let moves = oi_moves()?;
+      let drop = &moves[47];
+      let mov = drop.move_.ok_or("no move 47")?;
+      assert_eq!(None, mov.from);
+      assert_eq!(Kind::KA, mov.piece);
+      let time = drop.time.ok_or("no time on move 47")?;
+      assert_eq!(TimeFormat { h: None, m: 3, s: 0 }, time.now);
+      assert_eq!(TimeFormat { h: Some(4), m: 34, s: 0 }, time.total);
+      Ok(())
+   }
+
+   #[test]
+   fn fail_clock_past_255_hours() -> ErrStr<()> {
+      let game: W7A = "1.P7g-7f     256:00:00  00:00:00\n".parse()?;
+      let err = Converter::default().convert(&game).map(|_| ()).unwrap_err();
+      assert_eq!((BADCLOCK, Some(1)), (err.kind, err.n));
+      assert_eq!("A time of 921600 seconds is outside JKF's 0 to 255 hours", err.message);
+      let hours = |h: i64| time_format(Duration::hours(h)).map(|t| t.h);
+      assert_eq!(Ok(Some(255)), hours(255));
+      Ok(())
+   }
+
+   #[test]
+   fn test_handicap_initial() -> ErrStr<()> {
+      let game: W7A = "[Handicap \"Bishop\"]\n\
+                       1.P3c-3d     00:00:00  00:01:00\n\
+                       Resigns      00:02:00  00:01:00\n".parse()?;
+      let kifu = Converter::default().convert(&game)?.kifu();
+      assert_eq!(Some(Preset::PresetKA), kifu.initial.map(|i| i.preset));
+      let mov = kifu.moves[1].move_.ok_or("no move 1")?;
+      assert_eq!(JColor::White, mov.color);
+      // Black, to move, resigns
+      let time = kifu.moves[2].time.ok_or("no time on the result")?;
+      assert_eq!(TimeFormat { h: Some(0), m: 2, s: 0 }, time.total);
+      Ok(())
+   }
+
+   #[test]
+   fn test_sfen_initial() -> ErrStr<()> {
+      let game: W7A = "[SFEN \"8k/9/9/9/9/9/9/9/K8 b G 41\"]\n\
+                       41.G*2b      00:00:00  00:00:00\n".parse()?;
+      let jkf = Converter::default().convert(&game)?;
+      assert!(jkf.as_json().contains("\"preset\":\"OTHER\""));
+      let data = jkf.kifu().initial.and_then(|i| i.data).ok_or("no position")?;
+      assert_eq!((JColor::Black, 1), (data.color, data.hands[0].KI));
+      assert_eq!(Some(Kind::OU), data.board[0][0].kind);
+      Ok(())
+   }
+}
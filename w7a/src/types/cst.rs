@@ -0,0 +1,336 @@
+use std::{
+   borrow::Cow,
+   fmt
+};
+
+use super::w7a::{is_move, is_sealed, is_tag, is_terminal};
+
+// The concrete syntax tree keeps the w7a file as it was written: every line,
+// blank or not, in order, with its byte span in the source, and the spans of
+// the tokens on it. Nothing is dropped, so the source is the lines' spans,
+// end-of-lines included, laid end to end:
+
+// [Black "Habu Yoshiharu, Oi"]       TAG:     NAME, VALUE
+//                                    BLANK
+// The furigoma resulted in ...       COMMENT: TEXT
+// 1.P7g-7f     00:00:00  00:00:00    MOVE:    NUMBER, NOTATION, CLOCK, CLOCK
+// Sealed move                        SEALED:  TEXT
+// Resigns      07:55:00  07:59:00    RESULT:  ENDING, CLOCK, CLOCK
+
+// Whatever lies between the tokens (spaces, the '.', brackets and quotes) is
+// layout; it is in the line's span but in no token's.
+
+// The typed W7A is built from the tree: see W7A::from_cst.
+
+// ----- SPANS -------------------------------------------------------
+
+/// The bytes start..end of the source
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Default)]
+pub struct Span { pub start: usize, pub end: usize }
+
+impl Span {
+   pub fn new(start: usize, end: usize) -> Self { Span { start, end } }
+   pub fn len(&self) -> usize { self.end - self.start }
+   pub fn is_empty(&self) -> bool { self.start == self.end }
+}
+
+// ----- LINES AND TOKENS -------------------------------------------------
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum LineKind { TAG, BLANK, COMMENT, MOVE, SEALED, RESULT }
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum TokenKind { NAME, VALUE, TEXT, NUMBER, NOTATION, ENDING, CLOCK }
+
+use LineKind::*;
+use TokenKind::*;
+
+#[derive(Debug,Clone,PartialEq)]
+pub struct Token { pub kind: TokenKind, pub span: Span }
+
+#[derive(Debug,Clone,PartialEq)]
+pub struct Line {
+   pub number: usize,  // 1-based, as editors count them
+   pub kind: LineKind,
+   pub span: Span,     // the line, without its end-of-line
+   pub eol: Span,      // "\n", "\r\n", or nothing on the last line
+   pub tokens: Vec<Token>
+}
+
+impl Line {
+   /// The first token of the kind on this line
+   pub fn token(&self, kind: TokenKind) -> Option<&Token> {
+      self.tokens.iter().find(|t| t.kind == kind)
+   }
+
+   /// The spans of the clocks on this line, Black's then White's
+   pub fn clocks(&self) -> Vec<Span> {
+      self.tokens.iter().filter(|t| t.kind == CLOCK).map(|t| t.span).collect()
+   }
+}
+
+// ----- THE TREE -------------------------------------------------------
+
+// The tree borrows the source it is given (or owns the String it is given):
+// the lines and tokens are only spans into it.
+
+#[derive(Debug,Clone,PartialEq)]
+pub struct Cst<'a> {
+   name: Option<String>, // the file it was read from, for the diagnostics
+   source: Cow<'a, str>,
+   lines: Vec<Line>
+}
+
+impl<'a> Cst<'a> {
+   /// Every text is a w7a-tree; it is W7A::from_cst that finds fault
+   pub fn parse(source: impl Into<Cow<'a, str>>) -> Self {
+      let source = source.into();
+      let mut lines = Vec::new();
+      let mut start = 0;
+      let mut in_moves = false;
+      for (i, raw) in source.split_inclusive('\n').enumerate() {
+         let body = raw.trim_end_matches('\n').trim_end_matches('\r');
+         let span = Span::new(start, start + body.len());
+         let eol = Span::new(span.end, start + raw.len());
+         let (kind, tokens) = classify(body, start, in_moves);
+         in_moves = in_moves || kind == MOVE || kind == RESULT;
+         lines.push(Line { number: i + 1, kind, span, eol, tokens });
+         start += raw.len();
+      }
+      Cst { name: None, source, lines }
+   }
+
+   pub fn named(self, name: &str) -> Self {
+      Cst { name: Some(name.to_string()), ..self }
+   }
+
+   pub fn name(&self) -> Option<&str> { self.name.as_deref() }
+
+   /// Numbers the lines from first on, for a source that is part of a file
+   pub fn numbered_from(mut self, first: usize) -> Self {
+      for (i, line) in self.lines.iter_mut().enumerate() {
+         line.number = first + i;
+      }
+      self
+   }
+
+   pub fn source(&self) -> &str { &self.source }
+
+   pub fn lines(&self) -> &[Line] { &self.lines }
+
+   /// The text of the span; nothing, if the span is not of this source
+   pub fn text(&self, span: Span) -> &str {
+      self.source.get(span.start..span.end).unwrap_or("")
+   }
+
+   /// The line numbered n (the move-number, not the line-number)
+   pub fn move_line(&self, n: usize) -> Option<&Line> {
+      self.lines.iter().filter(|line| line.kind == MOVE).find(|line| {
+         line.token(NUMBER)
+             .is_some_and(|num| self.text(num.span).parse() == Ok(n))
+      })
+   }
+
+   /// The line of the game's result, e.g. "Resigns"
+   pub fn result_line(&self) -> Option<&Line> {
+      self.lines.iter().find(|line| line.kind == RESULT)
+   }
+
+   /// The header line of the tag, e.g. "Date"
+   pub fn tag_line(&self, tag: &str) -> Option<&Line> {
+      self.lines.iter().filter(|line| line.kind == TAG).find(|line| {
+         line.token(NAME).is_some_and(|name| self.text(name.span) == tag)
+      })
+   }
+
+   /// The line (and the column, 1-based, in characters) of a byte offset
+   pub fn locate(&self, offset: usize) -> Option<(&Line, usize)> {
+      self.lines.iter()
+          .find(|line| {
+             offset >= line.span.start
+                && (offset < line.eol.end || offset == line.span.end)
+          })
+          .map(|line| {
+             let upto = offset.min(line.span.end);
+             (line, self.text(Span::new(line.span.start, upto)).chars().count() + 1)
+          })
+   }
+
+   /// The source with span replaced by text, everything else untouched
+   pub fn edit(&self, span: Span, text: &str) -> Option<String> {
+      let before = self.source.get(..span.start)?;
+      let after = self.source.get(span.end..)?;
+      Some([before, text, after].concat())
+   }
+}
+
+impl fmt::Display for Cst<'_> {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      write!(f, "{}", self.source)
+   }
+}
+
+// ----- CLASSIFYING -------------------------------------------------------
+
+// Tags, [Key "value"], are only read before the first move: a bracket
+// further down, or one around anything else, "[Diagram 1]", is part of a
+// comment.
+fn classify(body: &str, offset: usize, in_moves: bool) -> (LineKind, Vec<Token>) {
+   if body.trim().is_empty() {
+      (BLANK, Vec::new())
+   } else if !in_moves && is_tag(body) {
+      (TAG, tag_tokens(body, offset))
+   } else if is_move(body) {
+      (MOVE, move_tokens(body, offset))
+   } else if is_terminal(body) {
+      (RESULT, word_tokens(body, offset, ENDING))
+   } else {
+      let lead = body.len() - body.trim_start().len();
+      let text = Span::new(offset + lead, offset + body.trim_end().len());
+      let kind = if in_moves && is_sealed(body) { SEALED } else { COMMENT };
+      (kind, vec![Token { kind: TEXT, span: text }])
+   }
+}
+
+// [Black "Habu Yoshiharu, Oi"]: the name and what is between the quotes
+fn tag_tokens(body: &str, offset: usize) -> Vec<Token> {
+   let mut tokens = Vec::new();
+   let open = body.find('[').map_or(0, |i| i + 1);
+   let quote = body.find('"');
+   let name_end = quote.or(body.rfind(']')).unwrap_or(body.len()).max(open);
+   let name = &body[open..name_end];
+   let lead = name.len() - name.trim_start().len();
+   if !name.trim().is_empty() {
+      let start = offset + open + lead;
+      tokens.push(Token { kind: NAME, span: Span::new(start, start + name.trim().len()) });
+   }
+   if let Some((q, len)) = quote.and_then(|q| Some((q, body[q + 1..].find('"')?))) {
+      let start = offset + q + 1;
+      tokens.push(Token { kind: VALUE, span: Span::new(start, start + len) });
+   }
+   tokens
+}
+
+// 75.G3bx3c  04:12:00  03:58:00: the number, the notation, then the clocks
+fn move_tokens(body: &str, offset: usize) -> Vec<Token> {
+   let mut tokens = word_tokens(body, offset, NOTATION);
+   if let Some(first) = tokens.first().cloned() {
+      let word = &body[first.span.start - offset..first.span.end - offset];
+      let dot = word.find('.').unwrap_or(0);
+      let number = Span::new(first.span.start, first.span.start + dot);
+      let notation = Span::new(number.end + 1, first.span.end);
+      tokens.splice(0..1, [Token { kind: NUMBER, span: number },
+                           Token { kind: NOTATION, span: notation }]);
+   }
+   tokens
+}
+
+// the first word is of the kind given, the rest are clocks
+fn word_tokens(body: &str, offset: usize, first: TokenKind) -> Vec<Token> {
+   words(body).into_iter()
+              .enumerate()
+              .map(|(i, (start, word))| {
+                 let kind = if i == 0 { first } else { CLOCK };
+                 Token { kind, span: Span::new(offset + start, offset + start + word.len()) }
+              })
+              .collect()
+}
+
+// the whitespace-separated words, with their byte offsets
+fn words(body: &str) -> Vec<(usize, &str)> {
+   let mut ans = Vec::new();
+   let mut start = None;
+   for (i, c) in body.char_indices() {
+      match (c.is_whitespace(), start) {
+         (true, Some(s)) => { ans.push((s, &body[s..i])); start = None; },
+         (false, None) => start = Some(i),
+         _ => ()
+      }
+   }
+   if let Some(s) = start { ans.push((s, &body[s..])); }
+   ans
+}
+
+// ----- TESTS -------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   use std::fs;
+
+   const OI: &str =
+      "../data/game_records/reijer_grimberger/2013-07-11-54th-oi-sen-game-1.w7a";
+
+   const SAMPLE: &str = "[Black \"Habu Yoshiharu, Oi\"]\r\n\
+                         \r\n\
+                         An opening comment\r\n\
+                         1.P7g-7f     00:00:00  00:00:00\r\n\
+                         Resigns      07:55:00  07:59:00";
+
+   #[test]
+   fn test_lossless() -> Result<(), String> {
+      let source = fs::read_to_string(OI).map_err(|e| e.to_string())?;
+      let cst = Cst::parse(&source);
+      let rebuilt: String =
+         cst.lines().iter()
+            .map(|line| format!("{}{}", cst.text(line.span), cst.text(line.eol)))
+            .collect();
+      assert_eq!(source, rebuilt);
+      assert_eq!(source, cst.to_string());
+      Ok(())
+   }
+
+   #[test]
+   fn test_line_kinds() {
+      let cst = Cst::parse(SAMPLE);
+      let kinds: Vec<LineKind> = cst.lines().iter().map(|l| l.kind).collect();
+      assert_eq!(vec![TAG, BLANK, COMMENT, MOVE, RESULT], kinds);
+      assert_eq!("\r\n", cst.text(cst.lines()[0].eol));
+      assert!(cst.lines()[4].eol.is_empty());
+   }
+
+   #[test]
+   fn test_sealed_only_among_moves() {
+      let cst = Cst::parse("Sealed move\n42.L1a-1b    03:31:00  04:12:00\n  sealed move \n");
+      let kinds: Vec<LineKind> = cst.lines().iter().map(|l| l.kind).collect();
+      assert_eq!(vec![COMMENT, MOVE, SEALED], kinds);
+   }
+
+   #[test]
+   fn test_tokens() {
+      let cst = Cst::parse(SAMPLE);
+      let tag = &cst.lines()[0];
+      assert_eq!("Black", cst.text(tag.token(NAME).unwrap().span));
+      assert_eq!("Habu Yoshiharu, Oi", cst.text(tag.token(VALUE).unwrap().span));
+      let mov = cst.move_line(1).unwrap();
+      assert_eq!("1", cst.text(mov.token(NUMBER).unwrap().span));
+      assert_eq!("P7g-7f", cst.text(mov.token(NOTATION).unwrap().span));
+      assert_eq!(2, mov.clocks().len());
+      let result = &cst.lines()[4];
+      assert_eq!("07:59:00", cst.text(result.clocks()[1]));
+   }
+
+   #[test]
+   fn test_locate() {
+      let cst = Cst::parse(SAMPLE);
+      let notation = cst.move_line(1).unwrap().token(NOTATION).unwrap().span;
+      let (line, col) = cst.locate(notation.start).unwrap();
+      assert_eq!((4, 3), (line.number, col));
+   }
+
+   #[test]
+   fn test_edit_one_move() {
+      let cst = Cst::parse(SAMPLE);
+      let notation = cst.move_line(1).unwrap().token(NOTATION).unwrap().span;
+      let edited = cst.edit(notation, "P2g-2f");
+      assert_eq!(Some(SAMPLE.replace("P7g-7f", "P2g-2f")), edited);
+      assert_eq!(None, cst.edit(Span::new(1, 1000), "P2g-2f"));
+   }
+
+   #[test]
+   fn test_bracket_after_moves_is_comment() {
+      let cst = Cst::parse("1.P7g-7f  00:00:00\n[Diagram 1]");
+      assert_eq!(COMMENT, cst.lines()[1].kind);
+   }
+}
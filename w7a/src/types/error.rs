@@ -0,0 +1,243 @@
+use std::fmt;
+
+use super::cst::{Cst, Span, TokenKind::*};
+
+// What went wrong reading, or converting, a w7a file, and where, written out
+// the way rustc writes its errors:
+
+// error[bad move]: No square 4z on the board
+//   --> games/54oi1.w7a:23:4
+//    |
+// 23 | 12.S3a-4z    00:10:00  00:11:00
+//    |    ^^^^^^
+//    = move 12
+
+// An error found by the parser knows its span straight away. One found by the
+// converter knows only the move (or header tag) it is about; locate() finds
+// that in the syntax tree.
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum ErrorKind {
+   READ, HEADER, BADMOVE, ILLEGAL, BADCLOCK, BADDATE, SEQUENCE, MISSING, OUTCOME,
+   ANNOTATION
+}
+
+use ErrorKind::*;
+
+/// A recovering read reports warnings, too: what it let pass
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum Severity { ERROR, WARNING }
+
+use Severity::*;
+
+impl fmt::Display for Severity {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      write!(f, "{}", match self { ERROR => "error", WARNING => "warning" })
+   }
+}
+
+impl fmt::Display for ErrorKind {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      let label = match self {
+         READ => "unreadable file", HEADER => "header syntax",
+         BADMOVE => "bad move", ILLEGAL => "illegal move",
+         BADCLOCK => "bad clock", BADDATE => "bad date",
+         SEQUENCE => "out of sequence", MISSING => "missing header",
+         OUTCOME => "wrong result", ANNOTATION => "wrong annotation"
+      };
+      write!(f, "{label}")
+   }
+}
+
+impl ErrorKind {
+   /// The stable, machine-readable name of the kind, e.g. for editors
+   pub fn code(&self) -> &'static str {
+      match self {
+         READ => "w7a::read", HEADER => "w7a::header",
+         BADMOVE => "w7a::bad-move", ILLEGAL => "w7a::illegal",
+         BADCLOCK => "w7a::bad-clock", BADDATE => "w7a::bad-date",
+         SEQUENCE => "w7a::sequence", MISSING => "w7a::missing-header",
+         OUTCOME => "w7a::result", ANNOTATION => "w7a::annotation"
+      }
+   }
+}
+
+#[derive(Debug,Clone,PartialEq)]
+pub struct W7AErr {
+   pub severity: Severity,
+   pub kind: ErrorKind,
+   pub message: String,
+   pub n: Option<usize>,  // the move number
+   site: Box<Site>        // boxed, to keep ErrW7A small
+}
+
+// where in the source the error is
+#[derive(Debug,Clone,PartialEq,Default)]
+struct Site {
+   file: Option<String>,
+   line: Option<usize>,
+   column: Option<usize>,  // 1-based, in characters
+   tag: Option<String>,    // the header tag, e.g. "Date"
+   source: Option<String>, // the offending line, for the caret
+   width: usize            // how many characters the caret underlines
+}
+
+pub type ErrW7A<T> = Result<T, W7AErr>;
+
+impl W7AErr {
+   pub fn new(kind: ErrorKind, message: &str) -> Self {
+      W7AErr { severity: ERROR, kind, message: message.to_string(), n: None,
+               site: Box::new(Site { width: 1, ..Site::default() }) }
+   }
+
+   pub fn file(&self) -> Option<&str> { self.site.file.as_deref() }
+   pub fn line(&self) -> Option<usize> { self.site.line }
+   pub fn column(&self) -> Option<usize> { self.site.column }
+
+   pub fn warning(self) -> Self {
+      W7AErr { severity: WARNING, ..self }
+   }
+
+   pub fn is_error(&self) -> bool { self.severity == ERROR }
+
+   pub fn on_move(mut self, n: usize) -> Self {
+      self.n = Some(n);
+      self
+   }
+
+   pub fn on_tag(mut self, tag: &str) -> Self {
+      self.site.tag = Some(tag.to_string());
+      self
+   }
+
+   pub fn in_file(mut self, file: &str) -> Self {
+      self.site.file = Some(file.to_string());
+      self
+   }
+
+   /// Points the error at the span of the syntax tree's source
+   pub fn at(mut self, cst: &Cst, span: Span) -> Self {
+      if let Some((line, column)) = cst.locate(span.start) {
+         let text = cst.text(line.span);
+         let upto = span.end.min(line.span.end).max(span.start);
+         self.site.line = Some(line.number);
+         self.site.column = Some(column);
+         self.site.width = cst.text(Span::new(span.start.min(upto), upto))
+                              .chars().count().max(1);
+         self.site.source = Some(text.to_string());
+      }
+      if self.site.file.is_none() {
+         self.site.file = cst.name().map(String::from);
+      }
+      self
+   }
+
+   /// Places the error on the result line (the ending), if the game has one
+   pub fn on_result(self, cst: &Cst) -> Self {
+      match cst.result_line() {
+         Some(line) => {
+            let span = line.token(ENDING).map_or(line.span, |t| t.span);
+            self.at(cst, span)
+         },
+         None => self.locate(cst)
+      }
+   }
+
+   pub fn locate(mut self, cst: &Cst) -> Self {
+      if self.site.line.is_some() { return self; }
+      let span = match (&self.n, &self.site.tag) {
+         (Some(n), _) => cst.move_line(*n).map(|line| {
+            line.token(NOTATION).map_or(line.span, |t| t.span)
+         }),
+         (None, Some(tag)) => cst.tag_line(tag).map(|line| {
+            line.token(VALUE).map_or(line.span, |t| t.span)
+         }),
+         (None, None) => None
+      };
+      match span {
+         Some(span) => self.at(cst, span),
+         None => {
+            if self.site.file.is_none() {
+               self.site.file = cst.name().map(String::from);
+            }
+            self
+         }
+      }
+   }
+}
+
+impl fmt::Display for W7AErr {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      write!(f, "{}[{}]: {}", self.severity, self.kind, self.message)?;
+      let Site { file, line, column, source, width, .. } = self.site.as_ref();
+      let name = file.as_deref().unwrap_or("<w7a>");
+      match (line, column, source) {
+         (Some(line), Some(column), Some(source)) => {
+            let gutter = " ".repeat(line.to_string().len());
+            write!(f, "\n{gutter}--> {name}:{line}:{column}")?;
+            write!(f, "\n{gutter} |")?;
+            write!(f, "\n{line} | {source}")?;
+            let lead: String = source.chars()
+                                     .take(column - 1)
+                                     .map(|c| if c == '\t' { '\t' } else { ' ' })
+                                     .collect();
+            write!(f, "\n{gutter} | {lead}{}", "^".repeat(*width))?;
+            if let Some(n) = self.n { write!(f, "\n{gutter} = move {n}")?; }
+         },
+         _ => {
+            if file.is_some() { write!(f, "\n --> {name}")?; }
+            if let Some(n) = self.n { write!(f, "\n  = move {n}")?; }
+         }
+      }
+      Ok(())
+   }
+}
+
+// so that a W7AErr goes wherever an ErrStr does
+impl From<W7AErr> for String {
+   fn from(err: W7AErr) -> String { err.to_string() }
+}
+
+// ----- TESTS -------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   const GAME: &str = "[Date \"July 10th 2013\"]\n\
+                       1.P7g-7f     00:00:00  00:00:00\n\
+                       2.S3a-4z     00:00:00  00:01:00\n";
+
+   #[test]
+   fn test_caret() {
+      let cst = Cst::parse(GAME).named("54oi1.w7a");
+      let notation = cst.move_line(2).unwrap().token(NOTATION).unwrap().span;
+      let err = W7AErr::new(BADMOVE, "No square 4z on the board")
+                      .at(&cst, notation)
+                      .on_move(2);
+      assert_eq!((Some(3), Some(3)), (err.line(), err.column()));
+      assert_eq!("error[bad move]: No square 4z on the board\n \
+                  --> 54oi1.w7a:3:3\n  \
+                  |\n\
+                  3 | 2.S3a-4z     00:00:00  00:01:00\n  \
+                  |   ^^^^^^\n  \
+                  = move 2", err.to_string());
+   }
+
+   #[test]
+   fn test_locate_move_and_tag() {
+      let cst = Cst::parse(GAME);
+      let illegal = W7AErr::new(ILLEGAL, "no piece on 7g").on_move(1).locate(&cst);
+      assert_eq!((Some(2), Some(3)), (illegal.line(), illegal.column()));
+      let date = W7AErr::new(BADDATE, "Cannot parse the year").on_tag("Date")
+                        .locate(&cst);
+      assert_eq!((Some(1), Some(8)), (date.line(), date.column()));
+   }
+
+   #[test]
+   fn test_unlocated() {
+      let err = W7AErr::new(READ, "no such file").in_file("nowhere.w7a");
+      assert_eq!("error[unreadable file]: no such file\n --> nowhere.w7a",
+                 String::from(err));
+   }
+}
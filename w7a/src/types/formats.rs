@@ -0,0 +1,203 @@
+use std::{
+   path::Path,
+   str::FromStr
+};
+
+use encoding_rs::SHIFT_JIS;
+
+use shogi_kifu_converter::converter::{ToCsa, ToKi2, ToKif};
+
+use book::{
+   err_utils::ErrStr,
+   json_utils::AsJSON
+};
+
+use super::{
+   conv::{Convert, Converter},
+   error::ErrW7A,
+   jkf::JKF,
+   w7a::W7A
+};
+
+// JKF is the hub: shogi-kifu-converter's writers take it to KIF, KI2 and CSA.
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum Format { KIF, KI2, CSA, JKF }
+
+impl FromStr for Format {
+   type Err = String;
+   fn from_str(s: &str) -> ErrStr<Self> {
+      match s.to_ascii_lowercase().as_str() {
+         "kif" => Ok(Format::KIF),
+         "ki2" => Ok(Format::KI2),
+         "csa" => Ok(Format::CSA),
+         "jkf" | "json" => Ok(Format::JKF),
+         _ => Err(format!("Unknown format {s}; choose kif, ki2, csa or jkf"))
+      }
+   }
+}
+
+impl Format {
+   /// The extension of a file written in this format
+   pub fn extension(&self) -> &'static str {
+      match self {
+         Format::KIF => "kif", Format::KI2 => "ki2",
+         Format::CSA => "csa", Format::JKF => "jkf"
+      }
+   }
+}
+
+pub fn to_jkf(game: &W7A) -> ErrW7A<JKF> {
+   Converter::default().convert(game)
+}
+
+pub fn to_json(game: &W7A) -> ErrW7A<String> {
+   Ok(to_jkf(game)?.as_json())
+}
+
+pub fn to_kif(game: &W7A) -> ErrW7A<String> {
+   Ok(to_jkf(game)?.kifu().to_kif_owned())
+}
+
+pub fn to_ki2(game: &W7A) -> ErrW7A<String> {
+   Ok(to_jkf(game)?.kifu().to_ki2_owned())
+}
+
+pub fn to_csa(game: &W7A) -> ErrW7A<String> {
+   Ok(to_jkf(game)?.kifu().to_csa_owned())
+}
+
+/// The game in the chosen format
+pub fn render(game: &W7A, format: Format) -> ErrW7A<String> {
+   match format {
+      Format::KIF => to_kif(game),
+      Format::KI2 => to_ki2(game),
+      Format::CSA => to_csa(game),
+      Format::JKF => to_json(game)
+   }
+}
+
+// ----- ENCODINGS -------------------------------------------------------
+
+// Traditional .kif (and .ki2) files are Shift_JIS; .kifu (and .ki2u) files
+// are UTF-8. Some Windows viewers want a BOM to believe a .kif is UTF-8.
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum Encoding { UTF8, UTF8BOM, SJIS }
+
+impl FromStr for Encoding {
+   type Err = String;
+   fn from_str(s: &str) -> ErrStr<Self> {
+      match s.to_ascii_lowercase().replace("_", "-").as_str() {
+         "utf-8" | "utf8" => Ok(Encoding::UTF8),
+         "utf-8-bom" | "utf8-bom" | "bom" => Ok(Encoding::UTF8BOM),
+         "shift-jis" | "sjis" | "cp932" => Ok(Encoding::SJIS),
+         _ => Err(format!("Unknown encoding {s}; \
+                           choose utf-8, utf-8-bom or shift_jis"))
+      }
+   }
+}
+
+/// The encoding the output file's extension calls for
+pub fn encoding_for(path: &Path) -> Encoding {
+   match path.extension().and_then(|ext| ext.to_str()) {
+      Some(ext) if ext.eq_ignore_ascii_case("kif")
+                   || ext.eq_ignore_ascii_case("ki2") => Encoding::SJIS,
+      _ => Encoding::UTF8
+   }
+}
+
+pub fn encode(text: &str, encoding: Encoding) -> ErrStr<Vec<u8>> {
+   match encoding {
+      Encoding::UTF8 => Ok(text.as_bytes().to_vec()),
+      Encoding::UTF8BOM => Ok(["\u{feff}", text].concat().into_bytes()),
+      Encoding::SJIS => {
+         let (bytes, _, unmappable) = SHIFT_JIS.encode(text);
+         if unmappable {
+            let bad: String = text.chars().filter(|c| {
+               SHIFT_JIS.encode(&c.to_string()).2
+            }).collect();
+            Err(format!("Cannot write {bad} in Shift_JIS; try utf-8 or utf-8-bom"))
+         } else {
+            Ok(bytes.into_owned())
+         }
+      }
+   }
+}
+
+// ----- TESTS -------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   use crate::types::w7a::load_w7a;
+
+   fn oi_game() -> ErrStr<W7A> {
+      let dir = "../data/game_records/reijer_grimberger";
+      Ok(load_w7a(&format!("{dir}/2013-07-11-54th-oi-sen-game-1.w7a"))?)
+   }
+
+   #[test]
+   fn test_parse_format() {
+      assert_eq!(Ok(Format::KI2), "KI2".parse());
+      assert!("pgn".parse::<Format>().is_err());
+      assert_eq!(Ok(Format::JKF), Format::JKF.extension().parse());
+   }
+
+   #[test]
+   fn test_encoding_for() {
+      assert_eq!(Encoding::SJIS, encoding_for(Path::new("games/54oi1.kif")));
+      assert_eq!(Encoding::UTF8, encoding_for(Path::new("games/54oi1.kifu")));
+      assert_eq!(Encoding::UTF8, encoding_for(Path::new("games/54oi1.json")));
+   }
+
+   #[test]
+   fn test_encode() -> ErrStr<()> {
+      assert_eq!(vec![0x95, 0xe0], encode("歩", Encoding::SJIS)?);
+      assert_eq!(vec![0xef, 0xbb, 0xbf, b'P'], encode("P", Encoding::UTF8BOM)?);
+      assert!(encode("Gōda", Encoding::SJIS).is_err());
+      Ok(())
+   }
+
+   #[test]
+   fn test_to_kif() -> ErrStr<()> {
+      let kif = to_kif(&oi_game()?)?;
+      assert!(kif.contains("先手：Habu Yoshiharu, Oi"));
+      assert!(kif.contains("手合割：平手"));
+      assert!(kif.contains("７六歩(77)"));
+      assert!(kif.contains("同　銀(88)"));
+      assert!(kif.contains("投了"));
+      Ok(())
+   }
+
+   #[test]
+   fn test_kif_time_control() -> ErrStr<()> {
+      let game: W7A = "[Black \"Habu\"]\n\
+                       [TimeControl \"10m+30s\"]\n\
+                       1.P7g-7f     00:09:00  00:00:00\n\
+                       2.P3c-3d     00:09:00  00:01:00\n\
+                       3.P2g-2f     00:10:20  00:01:00\n".parse()?;
+      let kif = to_kif(&game)?;
+      assert!(kif.contains("持ち時間：各10分（秒読み30秒）"), "{kif}");
+      assert_eq!(1, kif.matches("*秒読み").count(), "{kif}");
+      Ok(())
+   }
+
+   #[test]
+   fn test_to_ki2() -> ErrStr<()> {
+      let ki2 = to_ki2(&oi_game()?)?;
+      assert!(ki2.contains("▲７六歩"));
+      assert!(ki2.contains("金右")); // 67.G6hx7g
+      Ok(())
+   }
+
+   #[test]
+   fn test_to_csa() -> ErrStr<()> {
+      let csa = to_csa(&oi_game()?)?;
+      assert!(csa.contains("N+Habu Yoshiharu, Oi"));
+      assert!(csa.contains("+7776FU"));
+      assert!(csa.contains("%TORYO"));
+      Ok(())
+   }
+}
@@ -0,0 +1,248 @@
+use std::{
+   fmt,
+   str::FromStr
+};
+
+use book::err_utils::ErrStr;
+
+use super::{
+   board::Board,
+   cst::Cst,
+   error::{ErrorKind::{HEADER, OUTCOME}, W7AErr},
+   setup::setup,
+   w7a::{Color::{self, *}, Ending::JISHOGI, PieceType::{self, *}, W7A}
+};
+
+// Jishogi (impasse): when both kings have entered the enemy camp, neither is
+// likely to be mated, and the game is settled on points: 5 for a rook or a
+// bishop, promoted or not, 1 for any other piece but the king, counting the
+// side's pieces in enemy territory and in hand. The rule is the game's:
+
+// [Jishogi "27"]
+
+// Under the 24-point rule (the professionals', and the default), once both
+// kings have entered, a side with fewer than 24 points loses; otherwise the
+// game is drawn. Under the 27-point rule (the amateurs' declaration), the side
+// to move wins by declaring, if its king has entered and is not in check, it
+// has 10 pieces in enemy territory, bar the king, and it has 28 points, as
+// Black, or 27, as White.
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum Rule { TWENTYFOUR, TWENTYSEVEN }
+
+impl FromStr for Rule {
+   type Err = String;
+   fn from_str(s: &str) -> ErrStr<Self> {
+      match s.trim().trim_end_matches("-point") {
+         "24" => Ok(Rule::TWENTYFOUR),
+         "27" => Ok(Rule::TWENTYSEVEN),
+         _ => Err(format!("Unknown jishogi rule {s}; choose 24 or 27"))
+      }
+   }
+}
+
+impl fmt::Display for Rule {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      write!(f, "{}-point", match self { Rule::TWENTYFOUR => 24, Rule::TWENTYSEVEN => 27 })
+   }
+}
+
+/// The game's jishogi rule, as its Jishogi tag says; the 24-point rule, if
+/// it says nothing
+pub fn rule(game: &W7A) -> ErrStr<Rule> {
+   game.tag("Jishogi").map_or(Ok(Rule::TWENTYFOUR), str::parse)
+}
+
+/// What a side has toward an impasse
+#[derive(Debug,Clone,PartialEq)]
+pub struct Score {
+   pub points: usize,   // of its pieces in enemy territory and in hand
+   pub pieces: usize,   // in enemy territory, bar the king
+   pub entered: bool,   // its king is in enemy territory
+   pub in_check: bool
+}
+
+/// The ranks of the side's enemy territory: a to c for Black, g to i for White
+pub fn in_territory(side: &Color, rank: usize) -> bool {
+   match side { BLACK => rank <= 3, WHITE => rank >= 7 }
+}
+
+fn points(piece: PieceType) -> usize {
+   match piece { BISHOP | ROOK => 5, KING => 0, _ => 1 }
+}
+
+const IN_HAND: [PieceType; 7] = [PAWN, LANCE, KNIGHT, SILVER, GOLD, BISHOP, ROOK];
+
+pub fn score(board: &Board, side: &Color) -> Score {
+   let mut score = Score { points: 0, pieces: 0, entered: false,
+                           in_check: board.in_check(side) };
+   for ((_, y), occ) in board.occupied() {
+      if occ.color != *side || !in_territory(side, y) { continue; }
+      if occ.piece.piece == KING {
+         score.entered = true;
+      } else {
+         score.pieces += 1;
+         score.points += points(occ.piece.piece);
+      }
+   }
+   score.points += IN_HAND.iter().map(|p| board.in_hand(side, *p) * points(*p)).sum::<usize>();
+   score
+}
+
+/// Each side's score, Black's then White's, after each move of the game, by
+/// its number
+pub fn scores(game: &W7A) -> ErrStr<Vec<(usize, [Score; 2])>> {
+   let mut board = setup(&game.header)?.board;
+   game.moves.iter().map(|mov| {
+      board.apply(mov)?;
+      Ok((mov.n, [score(&board, &BLACK), score(&board, &WHITE)]))
+   }).collect()
+}
+
+/// Each side's score after move n
+pub fn score_at(game: &W7A, n: usize) -> ErrStr<[Score; 2]> {
+   scores(game)?.into_iter()
+                .find(|(m, _)| *m == n)
+                .map(|(_, scores)| scores)
+                .ok_or(format!("No move {n} in the game"))
+}
+
+fn name(side: &Color) -> &'static str { if *side == BLACK { "Black" } else { "White" } }
+
+/// The points, and whether the king has entered, of each side after each
+/// move, as CSV
+pub fn report(game: &W7A) -> ErrStr<String> {
+   let mut lines = vec!["move,black,white,black entered,white entered".to_string()];
+   for (n, [black, white]) in scores(game)? {
+      lines.push(format!("{n},{},{},{},{}", black.points, white.points,
+                         black.entered, white.entered));
+   }
+   Ok(lines.join("\n"))
+}
+
+/// Why the position is no impasse under the rule; Ok, if it is one
+pub fn impasse(board: &Board, rule: Rule) -> Result<(), String> {
+   match rule {
+      Rule::TWENTYFOUR => {
+         for side in [BLACK, WHITE] {
+            let score = score(board, &side);
+            if !score.entered {
+               return Err(format!("{}'s king has not entered", name(&side)));
+            }
+            if score.points < 24 {
+               return Err(format!("{} has {} points, and fewer than 24 lose",
+                                  name(&side), score.points));
+            }
+         }
+         Ok(())
+      },
+      Rule::TWENTYSEVEN => {
+         let side = &board.turn;
+         let score = score(board, side);
+         let needed = if *side == BLACK { 28 } else { 27 };
+         let why = if !score.entered {
+            "has not entered its king".to_string()
+         } else if score.in_check {
+            "is in check".to_string()
+         } else if score.pieces < 10 {
+            format!("has {} pieces in enemy territory, not 10", score.pieces)
+         } else if score.points < needed {
+            format!("has {} points, not {needed}", score.points)
+         } else {
+            return Ok(());
+         };
+         Err(format!("{}, declaring, {why}", name(side)))
+      }
+   }
+}
+
+/// Errors where the record ends in Jishogi and the position is no impasse
+pub fn lint_jishogi(cst: &Cst, game: &W7A) -> Vec<W7AErr> {
+   if game.outcome.as_ref().is_none_or(|o| o.ending != JISHOGI) {
+      return Vec::new();
+   }
+   let rule = match rule(game) {
+      Ok(rule) => rule,
+      Err(msg) => return vec![W7AErr::new(HEADER, &msg).on_tag("Jishogi").locate(cst)]
+   };
+   // an illegal replay is found, and reported, by the conversion
+   let Ok(mut board) = setup(&game.header).map(|setup| setup.board) else {
+      return Vec::new()
+   };
+   if game.moves.iter().any(|mov| board.apply(mov).is_err()) {
+      return Vec::new();
+   }
+   impasse(&board, rule).err().map(|why| {
+      W7AErr::new(OUTCOME, &format!("Jishogi, but under the {rule} rule {why}"))
+         .on_result(cst)
+   }).into_iter().collect()
+}
+
+// ----- TESTS -------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   use crate::types::setup::from_sfen;
+
+   // both kings in; Black's back rank and pawns in White's camp, and each
+   // side with a rook, a bishop and the rest in hand
+   const ENTERED: &str = "LNSG1GSNL/4K4/PPPPPPPPP/9/9/9/9/4k4/9";
+
+   fn board(hands: &str, turn: &str) -> ErrStr<Board> {
+      Ok(from_sfen(&format!("{ENTERED} {turn} {hands} 1"))?.board)
+   }
+
+   #[test]
+   fn test_score() -> ErrStr<()> {
+      let board = board("RB2Prb2g2s2n2l9p", "b")?;
+      let [black, white] = [score(&board, &BLACK), score(&board, &WHITE)];
+      assert_eq!(Score { points: 29, pieces: 17, entered: true, in_check: false }, black);
+      assert_eq!(Score { points: 27, pieces: 0, entered: true, in_check: false }, white);
+      Ok(())
+   }
+
+   #[test]
+   fn test_twenty_four() -> ErrStr<()> {
+      assert_eq!(Ok(()), impasse(&board("RB2Prb2g2s2n2l9p", "b")?, Rule::TWENTYFOUR));
+      let short = impasse(&board("RB2Prb9p", "b")?, Rule::TWENTYFOUR);
+      assert_eq!(Err("White has 19 points, and fewer than 24 lose".to_string()), short);
+      let out = from_sfen("4k4/9/9/9/9/9/9/9/4K4 b RB 1")?.board;
+      assert!(impasse(&out, Rule::TWENTYFOUR).is_err());
+      Ok(())
+   }
+
+   #[test]
+   fn test_twenty_seven() -> ErrStr<()> {
+      assert_eq!(Ok(()), impasse(&board("RB2P", "b")?, Rule::TWENTYSEVEN));
+      assert_eq!(Err("Black, declaring, has 27 points, not 28".to_string()),
+                 impasse(&board("RB", "b")?, Rule::TWENTYSEVEN));
+      assert!(impasse(&board("RB2P", "w")?, Rule::TWENTYSEVEN).is_err());
+      assert_eq!(Ok(Rule::TWENTYSEVEN), "27-point".parse());
+      Ok(())
+   }
+
+   #[test]
+   fn test_jishogi_result() -> ErrStr<()> {
+      let record = |hands: &str| {
+         format!("[SFEN \"{ENTERED} w {hands} 1\"]\n\
+                  [Jishogi \"27\"]\n\
+                  1.K5h-4h     00:00:00  00:00:00\n\
+                  Jishogi      00:00:00  00:00:00\n")
+      };
+      let lint = |source: String| -> ErrStr<Vec<(Option<usize>, String)>> {
+         let cst = Cst::parse(source);
+         let game = W7A::from_cst(&cst)?;
+         Ok(lint_jishogi(&cst, &game).into_iter().map(|e| (e.line(), e.message)).collect())
+      };
+      assert!(lint(record("RB2P"))?.is_empty());
+      assert_eq!(vec![(Some(4), "Jishogi, but under the 27-point rule Black, declaring, \
+                                 has 27 points, not 28".to_string())],
+                 lint(record("RB"))?);
+      let game: W7A = record("RB2P").parse()?;
+      assert_eq!(29, score_at(&game, 1)?[0].points);
+      assert_eq!(Some("1,29,0,true,true"), report(&game)?.lines().last());
+      Ok(())
+   }
+}
@@ -1,6 +1,8 @@
 use serde_variant::to_variant_name;
 
-use shogi_kifu_converter::jkf::Preset;
+use shogi_kifu_converter::jkf::{
+   Initial as KInitial, JsonKifuFormat, MoveFormat, Preset, StateFormat
+};
 
 use book::{
    json_utils::AsJSON,
@@ -9,13 +11,33 @@ use book::{
 
 use super::utils::Lookup;
 
-/*
 pub struct JKF {
    header: Header,
    initial: Initial,
-   moves: Vec<Move>
+   moves: Moves
+}
+pub fn mk_jkf(header: Header, initial: Initial, moves: Moves) -> JKF {
+   JKF { header, initial, moves }
+}
+
+impl JKF {
+   /// shogi-kifu-converter's model of this JKF, which it writes as KIF, KI2
+   /// or CSA
+   pub fn kifu(&self) -> JsonKifuFormat {
+      JsonKifuFormat {
+         header: self.header.fields.clone(),
+         initial: Some(self.initial.kifu()),
+         moves: self.moves.moves.clone()
+      }
+   }
+}
+
+impl AsJSON for JKF {
+   fn as_json(&self) -> String {
+      format!("{{\n{},\n{},\n{}\n}}", self.header.as_json(),
+              self.initial.as_json(), self.moves.as_json())
+   }
 }
-*/
 
 pub struct Header { fields: Lookup }
 pub fn mk_jhdr(fields: Lookup) -> Header { Header { fields } }
@@ -24,23 +46,48 @@ impl AsJSON for Header {
    fn as_json(&self) -> String { json_block("header", &self.fields) }
 }
 
-pub struct Initial { preset: Preset }
-pub fn mk_init(preset: Preset) -> Initial { Initial { preset } }
+/// The preset, or, for a position of its own (an SFEN), the position
+pub struct Initial { preset: Preset, data: Option<StateFormat> }
+pub fn mk_init(preset: Preset) -> Initial { Initial { preset, data: None } }
+pub fn mk_position(data: StateFormat) -> Initial {
+   Initial { preset: Preset::PresetOther, data: Some(data) }
+}
+
+impl Initial {
+   fn kifu(&self) -> KInitial { KInitial { preset: self.preset, data: self.data } }
+}
 
 impl Default for Initial {
-   fn default() -> Self { Self { preset: Preset::PresetHirate } }
+   fn default() -> Self { mk_init(Preset::PresetHirate) }
 }
 
 impl AsJSON for Initial {
    fn as_json(&self) -> String { 
+      if self.data.is_some() {
+         // serde-derived, so it cannot fail to serialize
+         let json = serde_json::to_string(&self.kifu()).unwrap_or_default();
+         return format!("\t{}: {json}", quot("initial"));
+      }
       let foo: Lookup = 
          [("preset".to_string(),
-           to_variant_name(&self.preset).unwrap().to_string())]
+           to_variant_name(&self.preset).unwrap_or("HIRATE").to_string())]
            .into_iter().collect();
       json_block("initial", &foo)
    }
 }
 
+/// The moves-array. As is JKF convention, the first entry carries no move,
+/// only the game comment.
+pub struct Moves { moves: Vec<MoveFormat> }
+pub fn mk_moves(moves: Vec<MoveFormat>) -> Moves { Moves { moves } }
+
+impl AsJSON for Moves {
+   fn as_json(&self) -> String {
+      let lin: Vec<String> = self.moves.iter().map(json_move).collect();
+      format!("\t{}: [\n\t\t{}\n\t]", quot("moves"), lin.join(",\n\t\t"))
+   }
+}
+
 // ----- helper functions in rendering JSON --------------------------------
 
 fn quotty(vals: &[&str]) -> Vec<String> { 
@@ -60,6 +107,11 @@ fn json_block(name: &str, attribs: &Lookup) -> String {
    format!("\t{}: {}", quot(name), json_hash(attribs))
 }
 
+// MoveFormat is serde-derived, so it cannot fail to serialize
+fn json_move(mov: &MoveFormat) -> String {
+   serde_json::to_string(mov).unwrap_or_default()
+}
+
 // ----- TESTS -------------------------------------------------------
 
 #[cfg(test)]
@@ -92,5 +144,13 @@ mod tests {
       assert!(json.contains("2013"));
       assert!(json.contains("54th Oi-sen"));
    }
+
+   #[test]
+   fn test_moves_json() {
+      let moves = mk_moves(vec![MoveFormat::default(), MoveFormat::default()]);
+      let json = moves.as_json();
+      assert!(json.starts_with("\t\"moves\": ["));
+      assert_eq!(2, json.matches("{}").count());
+   }
 }
 
@@ -0,0 +1,207 @@
+use shogi_kifu_converter::jkf::Relative;
+
+use book::err_utils::ErrStr;
+
+use super::{
+   board::{Board, Square, forward, square},
+   w7a::{Color, Color::*, Move, PieceType::*}
+};
+
+// KIF and KI2 say which of two (or more) like pieces moved to a square with a
+// marker after the piece:
+
+// 上 (U), 引 (D), 寄 (M): the piece moved forward, backward or sideways
+// 左 (L), 右 (R), 直 (C): the piece on the mover's left, right, or the one
+//                         that moved straight forward
+// 打 (H): the piece was dropped, when a piece on the board could have moved
+//         there instead
+
+// The markers are computed from the board BEFORE the move is played.
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+enum Vertical { UP, SIDEWAYS, DOWN }
+
+use Vertical::*;
+
+/// The relative-marker for the move, if the move needs one
+pub fn relative(board: &Board, mov: &Move) -> ErrStr<Option<Relative>> {
+   let c = board.turn.clone();
+   let to = square(&mov.to)?;
+   let rivals = |from: Option<Square>| -> Vec<Square> {
+      board.occupied()
+           .into_iter()
+           .filter(|(sq, occ)| {
+              Some(*sq) != from && occ.color == c && occ.piece == mov.piece
+                 && board.reaches(*sq, to)
+           })
+           .map(|(sq, _)| sq)
+           .collect()
+   };
+   match &mov.from {
+      None => Ok((!rivals(None).is_empty()).then_some(Relative::H)),
+      Some(pos) => {
+         let from = square(pos)?;
+         let others = rivals(Some(from));
+         Ok(if others.is_empty() {
+            None
+         } else {
+            let major = mov.piece.promoted
+                           && matches!(mov.piece.piece, BISHOP | ROOK);
+            disambiguate(&c, from, to, &others, major)
+         })
+      }
+   }
+}
+
+fn disambiguate(c: &Color, from: Square, to: Square, others: &[Square],
+                major: bool) -> Option<Relative> {
+   let v = vertical(c, from, to);
+   let plain = match v { UP => Relative::U, SIDEWAYS => Relative::M, DOWN => Relative::D };
+   let same_v: Vec<Square> =
+      others.iter().copied().filter(|sq| vertical(c, *sq, to) == v).collect();
+   if same_v.is_empty() {
+      return Some(plain);
+   }
+   // 直 is for golds and silvers moving straight up, never for 竜 or 馬
+   if !major && v == UP && from.0 == to.0 {
+      return Some(Relative::C);
+   }
+   match horizontal(c, from, others) {
+      Some(h) => Some(h),
+      // between rivals moving the same way, the way it moves is all there is
+      None if horizontal(c, from, &same_v).is_none() => Some(plain),
+      None => {
+         let left = horizontal(c, from, &same_v) == Some(Relative::L);
+         Some(match (left, v) {
+            (true, UP) => Relative::LU,
+            (true, SIDEWAYS) => Relative::LM,
+            (true, DOWN) => Relative::LD,
+            (false, UP) => Relative::RU,
+            (false, SIDEWAYS) => Relative::RM,
+            (false, DOWN) => Relative::RD
+         })
+      }
+   }
+}
+
+// Moving toward the opponent is up, whichever side one plays
+fn vertical(c: &Color, from: Square, to: Square) -> Vertical {
+   let dy = (to.1 as i32 - from.1 as i32) * forward(c);
+   if dy > 0 { UP } else if dy == 0 { SIDEWAYS } else { DOWN }
+}
+
+// 左 or 右 if the mover stands left or right of all the others, as the mover
+// sees the board: Black's left is file 9, White's left is file 1
+fn horizontal(c: &Color, from: Square, others: &[Square]) -> Option<Relative> {
+   let leftness = |sq: &Square| -> i32 {
+      if *c == BLACK { sq.0 as i32 } else { 10 - sq.0 as i32 }
+   };
+   let me = leftness(&from);
+   if others.iter().all(|sq| leftness(sq) < me) {
+      Some(Relative::L)
+   } else if others.iter().all(|sq| leftness(sq) > me) {
+      Some(Relative::R)
+   } else {
+      None
+   }
+}
+
+// ----- TESTS -------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   use chrono::Duration;
+
+   use crate::types::w7a::{Piece, PieceType, Position, load_w7a};
+
+   fn pos(x: usize, y: &str) -> Position { Position { x, y: y.to_string() } }
+
+   fn mk_move(n: usize, piece: PieceType, from: Option<Position>,
+              to: Position, capture: bool) -> Move {
+      let color = if n % 2 == 1 { BLACK } else { WHITE };
+      Move { n, color, piece: Piece { promoted: false, piece }, drop: from.is_none(),
+             from, to, capture, promote: None,
+             total_time: Duration::zero(), other_time: Duration::zero(),
+             comment: None }
+   }
+
+   // the relative-marker of move n of the 54th Oi-sen, game 1
+   fn oi_relative(n: usize) -> ErrStr<Option<Relative>> {
+      let dir = "../data/game_records/reijer_grimberger";
+      let game = load_w7a(&format!("{dir}/2013-07-11-54th-oi-sen-game-1.w7a"))?;
+      let mut board = Board::default();
+      for mov in game.moves.iter().take(n - 1) {
+         board.apply(mov)?;
+      }
+      relative(&board, &game.moves[n - 1])
+   }
+
+   #[test]
+   fn test_no_marker_needed() -> ErrStr<()> {
+      assert_eq!(None, oi_relative(1)?);
+      assert_eq!(None, oi_relative(69)?); // G*3b: no gold could go there
+      Ok(())
+   }
+
+   #[test]
+   fn test_gold_right() -> ErrStr<()> {
+      assert_eq!(Some(Relative::R), oi_relative(67)?); // G6hx7g, not G7h
+      Ok(())
+   }
+
+   #[test]
+   fn test_gold_straight() {
+      let mut board = Board::empty();
+      board.put((7, 8), BLACK, GOLD);
+      board.put((6, 8), BLACK, GOLD);
+      let mov = mk_move(1, GOLD, Some(pos(7, "h")), pos(7, "g"), false);
+      assert_eq!(Ok(Some(Relative::C)), relative(&board, &mov));
+   }
+
+   #[test]
+   fn test_three_golds() {
+      let mut board = Board::empty();
+      for x in [6, 5, 4] { board.put((x, 9), BLACK, GOLD); }
+      let marker = |x: usize| {
+         relative(&board, &mk_move(1, GOLD, Some(pos(x, "i")), pos(5, "h"), false))
+      };
+      assert_eq!([Ok(Some(Relative::L)), Ok(Some(Relative::C)), Ok(Some(Relative::R))],
+                 [marker(6), marker(5), marker(4)]);
+      // the mover between two rivals, all three moving up, not straight
+      assert_eq!(Some(Relative::U),
+                 disambiguate(&BLACK, (5, 9), (4, 8), &[(6, 9), (4, 9)], false));
+   }
+
+   #[test]
+   fn test_sideways_and_up() {
+      let mut board = Board::empty();
+      board.put((4, 9), BLACK, GOLD);
+      board.put((6, 8), BLACK, GOLD);
+      let yoru = mk_move(1, GOLD, Some(pos(6, "h")), pos(5, "h"), false);
+      assert_eq!(Ok(Some(Relative::M)), relative(&board, &yoru));
+      let agaru = mk_move(1, GOLD, Some(pos(4, "i")), pos(5, "h"), false);
+      assert_eq!(Ok(Some(Relative::U)), relative(&board, &agaru));
+   }
+
+   #[test]
+   fn test_white_left() {
+      let mut board = Board::empty();
+      board.put((4, 1), WHITE, SILVER);
+      board.put((6, 1), WHITE, SILVER);
+      board.turn = WHITE;
+      let mov = mk_move(2, SILVER, Some(pos(4, "a")), pos(5, "b"), false);
+      assert_eq!(Ok(Some(Relative::L)), relative(&board, &mov));
+   }
+
+   #[test]
+   fn test_drop_marker() {
+      let mut board = Board::empty();
+      board.put((5, 9), BLACK, GOLD);
+      let near = mk_move(1, GOLD, None, pos(5, "h"), false);
+      assert_eq!(Ok(Some(Relative::H)), relative(&board, &near));
+      let far = mk_move(1, GOLD, None, pos(5, "e"), false);
+      assert_eq!(Ok(None), relative(&board, &far));
+   }
+}
@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+
+use book::err_utils::ErrStr;
+
+use super::{
+   cst::Cst,
+   error::{ErrorKind::OUTCOME, W7AErr},
+   setup::setup,
+   w7a::{Color::{self, *}, Ending::SENNICHITE, W7A}
+};
+
+// Sennichite: the same position (board, hands and side to move) four times
+// over ends the game, drawn; unless one side gave check with every move of
+// the repetition, when that side loses instead.
+
+// The replay hashes each position (Board::hash) and counts how often it has
+// come up; a record ending in Sennichite must reach the fourth time on its
+// last move, and one that reaches it must end there.
+
+/// The fourfold repetition: the move that makes it, and the side, if any,
+/// that checked throughout it
+#[derive(Debug,Clone,PartialEq)]
+pub struct Repetition {
+   pub n: usize,
+   pub checking: Option<Color>
+}
+
+/// The first fourfold repetition of the game's replay, if it comes to one
+pub fn repetition(game: &W7A) -> ErrStr<Option<Repetition>> {
+   let mut board = setup(&game.header)?.board;
+   // the positions, by hash: when each came up, in moves played
+   let mut seen: HashMap<u64, Vec<usize>> = HashMap::new();
+   seen.entry(board.hash()).or_default().push(0);
+   let mut checks = Vec::new();  // did the i-th move give check?
+   for (i, mov) in game.moves.iter().enumerate() {
+      board.apply(mov)?;
+      checks.push(board.in_check(&board.turn));
+      let times = seen.entry(board.hash()).or_default();
+      times.push(i + 1);
+      if times.len() == 4 {
+         // the moves since the position first came up
+         let cycle = times[0]..=i;
+         let checking = [BLACK, WHITE].into_iter().find(|side| {
+            cycle.clone()
+                 .filter(|j| game.moves[*j].color == *side)
+                 .all(|j| checks[j])
+         });
+         return Ok(Some(Repetition { n: mov.n, checking }));
+      }
+   }
+   Ok(None)
+}
+
+/// Errors where the record's result and the repetition disagree
+pub fn lint_repetition(cst: &Cst, game: &W7A) -> Vec<W7AErr> {
+   // an illegal replay is found, and reported, by the conversion
+   let Ok(found) = repetition(game) else { return Vec::new() };
+   let last = game.moves.last().map(|m| m.n);
+   let ending = game.outcome.as_ref().map(|o| &o.ending);
+   let at_result = |msg: &str| W7AErr::new(OUTCOME, msg).on_result(cst);
+   let err = match (found, ending) {
+      (None, Some(SENNICHITE)) =>
+         at_result("Sennichite, but no position comes up four times"),
+      (None, _) => return Vec::new(),
+      (Some(rep), _) if Some(rep.n) != last => {
+         let msg = format!("Move {} repeats the position a fourth time, {}; yet \
+                            the record goes on", rep.n, verdict(&rep));
+         W7AErr::new(OUTCOME, &msg).on_move(rep.n).locate(cst)
+      },
+      (Some(Repetition { checking: None, .. }), Some(SENNICHITE)) => return Vec::new(),
+      (Some(rep), Some(SENNICHITE)) =>
+         at_result(&format!("Sennichite, but the repetition is {}", verdict(&rep))),
+      // the checking side may lose as it will: resigning, or on time
+      (Some(Repetition { checking: Some(_), .. }), Some(_)) => return Vec::new(),
+      (Some(rep), Some(ending)) =>
+         at_result(&format!("{ending}, but move {} repeats the position a fourth \
+                             time: sennichite", rep.n)),
+      (Some(rep), None) => {
+         let msg = format!("Move {} repeats the position a fourth time, {}; the \
+                            record has no result", rep.n, verdict(&rep));
+         W7AErr::new(OUTCOME, &msg).warning().on_move(rep.n).locate(cst)
+      }
+   };
+   vec![err]
+}
+
+fn verdict(rep: &Repetition) -> String {
+   match &rep.checking {
+      None => "sennichite".to_string(),
+      Some(side) => {
+         let side = if *side == BLACK { "Black" } else { "White" };
+         format!("perpetual check by {side}, who loses")
+      }
+   }
+}
+
+// ----- TESTS -------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   use crate::types::error::ErrorKind;
+
+   // the rooks, out and back, three times over: the start comes up again
+   // after moves 4, 8 and 12
+   fn shuffle() -> String {
+      let mut moves = String::new();
+      for n in 1..=12 {
+         let rook = ["R2h-3h", "R8b-7b", "R3h-2h", "R7b-8b"][(n - 1) % 4];
+         moves.push_str(&format!("{n}.{rook}       00:00:00  00:00:00\n"));
+      }
+      moves
+   }
+
+   // Black's rook checks from rank a and rank b, by turns, as White's king
+   // steps between them
+   const PERPETUAL: &str = "[SFEN \"4k3R/9/9/9/9/9/9/9/4K4 w - 1\"]\n";
+
+   fn perpetual() -> String {
+      let mut moves = PERPETUAL.to_string();
+      for n in 1..=12 {
+         let mov = ["K5a-5b", "R1a-1b", "K5b-5a", "R1b-1a"][(n - 1) % 4];
+         moves.push_str(&format!("{n}.{mov}       00:00:00  00:00:00\n"));
+      }
+      moves
+   }
+
+   fn kinds(source: &str) -> ErrStr<Vec<(ErrorKind, Option<usize>)>> {
+      let cst = Cst::parse(source);
+      let game = W7A::from_cst(&cst)?;
+      Ok(lint_repetition(&cst, &game).iter().map(|e| (e.kind, e.line())).collect())
+   }
+
+   #[test]
+   fn test_fourfold() -> ErrStr<()> {
+      let game: W7A = shuffle().parse()?;
+      assert_eq!(Some(Repetition { n: 12, checking: None }), repetition(&game)?);
+      let short: W7A = shuffle().lines().take(11).collect::<Vec<_>>().join("\n").parse()?;
+      assert_eq!(None, repetition(&short)?);
+      Ok(())
+   }
+
+   #[test]
+   fn test_perpetual_check() -> ErrStr<()> {
+      let game: W7A = perpetual().parse()?;
+      assert_eq!(Some(Repetition { n: 12, checking: Some(BLACK) }), repetition(&game)?);
+      Ok(())
+   }
+
+   #[test]
+   fn test_result_cross_checked() -> ErrStr<()> {
+      let ending = "Sennichite   00:00:00  00:00:00\n";
+      assert_eq!(Vec::<(ErrorKind, Option<usize>)>::new(),
+                 kinds(&format!("{}{ending}", shuffle()))?);
+      assert_eq!(vec![(OUTCOME, Some(13))],
+                 kinds(&format!("{}Resigns      00:00:00  00:00:00\n", shuffle()))?);
+      let early: String = shuffle().lines().take(8).map(|l| format!("{l}\n")).collect();
+      assert_eq!(vec![(OUTCOME, Some(9))], kinds(&format!("{early}{ending}"))?);
+      assert_eq!(vec![(OUTCOME, Some(14))], kinds(&format!("{}{ending}", perpetual()))?);
+      let on = format!("{}13.P7g-7f     00:00:00  00:00:00\n", shuffle());
+      assert_eq!(vec![(OUTCOME, Some(12))], kinds(&on)?);
+      Ok(())
+   }
+}
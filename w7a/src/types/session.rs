@@ -0,0 +1,96 @@
+use chrono::{Days, NaiveDate};
+
+use super::{
+   conv::date_range,
+   w7a::W7A
+};
+
+// A title game of two days is played in two sessions. The player to move at
+// the end of the first day seals the move, to be played on the second:
+
+// [Date "July 10th and 11th 2013"]
+// ...
+// 42.L1a-1b    03:31:00  04:12:00
+// Sealed move
+
+// (or [Sealed "42"] in the header). The game's sessions end at its sealed
+// moves, and take their days, in turn, from the Date's range.
+
+/// A session of play: its first and last moves and, if the Date says, its day
+#[derive(Debug,Clone,PartialEq)]
+pub struct Session {
+   pub first: usize,
+   pub last: usize,
+   pub day: Option<NaiveDate>
+}
+
+pub fn sessions(game: &W7A) -> Vec<Session> {
+   let (Some(opening), Some(closing)) = (game.moves.first(), game.moves.last()) else {
+      return Vec::new();
+   };
+   let range = game.tag("Date").and_then(|dt| date_range(dt).ok());
+   let day = |i: usize| range.and_then(|(first, last)| {
+      first.checked_add_days(Days::new(i as u64)).filter(|day| *day <= last)
+   });
+   let mut sessions = Vec::new();
+   let mut first = opening.n;
+   for &sealed in game.sealed.iter().filter(|n| (opening.n..closing.n).contains(*n)) {
+      sessions.push(Session { first, last: sealed, day: day(sessions.len()) });
+      first = sealed + 1;
+   }
+   sessions.push(Session { first, last: closing.n, day: day(sessions.len()) });
+   sessions
+}
+
+/// The day a game played over days ended: its last session's or, with no
+/// move sealed, the last of the Date
+pub fn last_day(game: &W7A) -> Option<NaiveDate> {
+   let (first, last) = game.tag("Date").and_then(|dt| date_range(dt).ok())?;
+   let played = sessions(game);
+   let day = match played.as_slice() {
+      [_, .., session] => session.day.unwrap_or(last),
+      _ => last
+   };
+   (day != first).then_some(day)
+}
+
+// ----- TESTS -------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   use book::err_utils::ErrStr;
+
+   // a game of three days, sealed at the end of the first
+   const SEALED: &str = "[Date \"July 10th to 12th 2013\"]\n\
+                         1.P7g-7f     00:00:00  00:00:00\n\
+                         2.P3c-3d     00:00:00  00:00:00\n\
+                         Sealed move\n\
+                         3.P2g-2f     00:00:00  00:00:00\n\
+                         4.P8c-8d     00:00:00  00:00:00\n";
+
+   #[test]
+   fn test_sealed_sessions() -> ErrStr<()> {
+      let game: W7A = SEALED.parse()?;
+      let day = |d| NaiveDate::from_ymd_opt(2013, 7, d);
+      assert_eq!(vec![Session { first: 1, last: 2, day: day(10) },
+                      Session { first: 3, last: 4, day: day(11) }], sessions(&game));
+      assert_eq!(day(11), last_day(&game));
+      let unsealed: W7A = SEALED.replace("Sealed move\n", "").parse()?;
+      assert_eq!(day(12), last_day(&unsealed));
+      Ok(())
+   }
+
+   #[test]
+   fn test_unsealed() -> ErrStr<()> {
+      let game: W7A = "[Date \"June 4th 2013\"]\n\
+                       1.P7g-7f     00:00:00  00:00:00\n\
+                       2.P3c-3d     00:00:00  00:00:00\n".parse()?;
+      assert_eq!(vec![Session { first: 1, last: 2,
+                                day: NaiveDate::from_ymd_opt(2013, 6, 4) }],
+                 sessions(&game));
+      assert_eq!(None, last_day(&game));
+      Ok(())
+   }
+}
@@ -0,0 +1,254 @@
+use serde_variant::to_variant_name;
+
+use shogi_kifu_converter::jkf::Preset::{self, *};
+
+use book::err_utils::ErrStr;
+
+use super::{
+   board::{Board, Occupant, Square},
+   w7a::{Color::*, Header, Piece, PieceType::*, parse_piece_type, piece_letter}
+};
+
+// Most games start from the even position, Black to move. A handicap game
+// starts without some of White's pieces, and White moves first:
+
+// [Handicap "Bishop"]
+
+// A problem, or a game taken up partway through, starts from its SFEN: the
+// board, the side to move, the pieces in hand, and the number of the move
+// to be played:
+
+// [SFEN "lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1"]
+
+/// The handicaps: the JKF preset, the w7a name, and the squares of the pieces
+/// White gives up
+pub const HANDICAPS: [(Preset, &str, &[Square]); 10] = [
+   (PresetKY, "Lance", &[(1, 1)]),
+   (PresetKYR, "Right lance", &[(9, 1)]),
+   (PresetKA, "Bishop", &[(2, 2)]),
+   (PresetHI, "Rook", &[(8, 2)]),
+   (PresetHIKY, "Rook and lance", &[(8, 2), (1, 1)]),
+   (Preset2, "Two pieces", &[(8, 2), (2, 2)]),
+   (Preset4, "Four pieces", &[(8, 2), (2, 2), (9, 1), (1, 1)]),
+   (Preset6, "Six pieces", &[(8, 2), (2, 2), (9, 1), (1, 1), (8, 1), (2, 1)]),
+   (Preset8, "Eight pieces",
+    &[(8, 2), (2, 2), (9, 1), (1, 1), (8, 1), (2, 1), (7, 1), (3, 1)]),
+   (Preset10, "Ten pieces",
+    &[(8, 2), (2, 2), (9, 1), (1, 1), (8, 1), (2, 1), (7, 1), (3, 1), (6, 1), (4, 1)])
+];
+
+/// Where the game starts: the board, with the side to move; the number of
+/// the first move; and the JKF preset (OTHER, for an SFEN)
+#[derive(Debug,Clone,PartialEq)]
+pub struct Setup {
+   pub board: Board,
+   pub first: usize,
+   pub preset: Preset
+}
+
+impl Default for Setup {
+   fn default() -> Self {
+      Setup { board: Board::default(), first: 1, preset: PresetHirate }
+   }
+}
+
+/// The setup of the game, as its Handicap or SFEN tag says
+pub fn setup(header: &Header) -> ErrStr<Setup> {
+   match (header.header.get("Handicap"), header.header.get("SFEN")) {
+      (Some(_), Some(_)) =>
+         Err("A game starts from a Handicap or from an SFEN, not both".to_string()),
+      (Some(name), None) => handicap(name),
+      (None, Some(sfen)) => from_sfen(sfen),
+      (None, None) => Ok(Setup::default())
+   }
+}
+
+/// The handicap by its name, or by its JKF preset, e.g. "Bishop" or "KA"
+pub fn handicap(name: &str) -> ErrStr<Setup> {
+   let (preset, _, squares) = HANDICAPS.iter().find(|(preset, w7a, _)| {
+      w7a.eq_ignore_ascii_case(name.trim())
+         || to_variant_name(preset).is_ok_and(|jkf| jkf.eq_ignore_ascii_case(name.trim()))
+   }).ok_or_else(|| {
+      let names: Vec<&str> = HANDICAPS.iter().map(|(_, w7a, _)| *w7a).collect();
+      format!("Unknown handicap {name}; choose one of {}", names.join(", "))
+   })?;
+   let mut board = Board::default();
+   for sq in squares.iter() { board.set(*sq, None); }
+   board.turn = WHITE;
+   Ok(Setup { board, first: 1, preset: *preset })
+}
+
+/// The name of the handicap of the JKF preset, if w7a has one
+pub fn handicap_name(preset: Preset) -> Option<&'static str> {
+   HANDICAPS.iter().find(|(p, _, _)| *p == preset).map(|(_, name, _)| *name)
+}
+
+// ----- SFEN -------------------------------------------------------
+
+/// The setup of an SFEN: the ranks, a to i, each from file 9 to file 1,
+/// Black's pieces in capitals; the side to move; the pieces in hand, or "-";
+/// and, optionally, the number of the move to be played
+pub fn from_sfen(sfen: &str) -> ErrStr<Setup> {
+   let fields: Vec<&str> = sfen.split_whitespace().collect();
+   let [ranks, side, hands, rest @ ..] = fields.as_slice() else {
+      return Err(format!("SFEN {sfen} needs a board, a side to move and the \
+                          pieces in hand"));
+   };
+   let mut board = Board::empty();
+   let ranks: Vec<&str> = ranks.split('/').collect();
+   if ranks.len() != 9 {
+      return Err(format!("SFEN board has {} ranks, not 9", ranks.len()));
+   }
+   for (y, rank) in ranks.iter().enumerate() {
+      place_rank(&mut board, y + 1, rank)?;
+   }
+   board.turn = match *side {
+      "b" => BLACK,
+      "w" => WHITE,
+      _ => return Err(format!("SFEN side to move is b or w, not {side}"))
+   };
+   if *hands != "-" { place_hands(&mut board, hands)?; }
+   let first = match rest.first() {
+      Some(n) => n.parse().ok().filter(|n| *n > 0)
+                  .ok_or(format!("SFEN move number {n} is not a number"))?,
+      None => 1
+   };
+   Ok(Setup { board, first, preset: PresetOther })
+}
+
+/// The SFEN of the board, from move 1
+pub fn to_sfen(board: &Board) -> String {
+   let ranks: Vec<String> = (1..=9).map(|y| {
+      let mut rank = String::new();
+      let mut empty = 0;
+      for x in (1..=9).rev() {
+         let Some(occ) = board.at((x, y)) else { empty += 1; continue };
+         if empty > 0 { rank.push_str(&empty.to_string()); }
+         empty = 0;
+         if occ.piece.promoted { rank.push('+'); }
+         let letter = piece_letter(occ.piece.piece);
+         rank.push(if occ.color == BLACK { letter } else { letter.to_ascii_lowercase() });
+      }
+      if empty > 0 { rank.push_str(&empty.to_string()); }
+      rank
+   }).collect();
+   let mut hands = String::new();
+   for c in [BLACK, WHITE] {
+      for piece in [ROOK, BISHOP, GOLD, SILVER, KNIGHT, LANCE, PAWN] {
+         let n = board.in_hand(&c, piece);
+         if n > 1 { hands.push_str(&n.to_string()); }
+         if n > 0 {
+            let letter = piece_letter(piece);
+            hands.push(if c == BLACK { letter } else { letter.to_ascii_lowercase() });
+         }
+      }
+   }
+   if hands.is_empty() { hands.push('-'); }
+   let side = if board.turn == BLACK { 'b' } else { 'w' };
+   format!("{} {side} {hands} 1", ranks.join("/"))
+}
+
+// e.g. "ln1g3+Rl": pieces, promoted with '+', and runs of empty squares
+fn place_rank(board: &mut Board, y: usize, rank: &str) -> ErrStr<()> {
+   let bad = || format!("SFEN rank {rank} is not nine squares");
+   let mut x: i32 = 10;  // the files run from 9 down to 1
+   let mut promoted = false;
+   for c in rank.chars() {
+      match c {
+         '+' if !promoted => { promoted = true; continue; },
+         '1'..='9' if !promoted => x -= c as i32 - '0' as i32,
+         _ => {
+            x -= 1;
+            let piece = Piece { promoted, piece: parse_piece_type(c)? };
+            let color = if c.is_ascii_uppercase() { BLACK } else { WHITE };
+            board.set((x as usize, y), Some(Occupant { color, piece }));
+         }
+      }
+      promoted = false;
+      if x < 1 { return Err(bad()); }
+   }
+   if x == 1 && !promoted { Ok(()) } else { Err(bad()) }
+}
+
+// e.g. "2Pb": counts, then pieces, Black's in capitals
+fn place_hands(board: &mut Board, hands: &str) -> ErrStr<()> {
+   let mut count = String::new();
+   for c in hands.chars() {
+      if c.is_ascii_digit() {
+         count.push(c);
+         continue;
+      }
+      let piece = parse_piece_type(c)?;
+      if piece == KING {
+         return Err("SFEN has a king in hand".to_string());
+      }
+      let color = if c.is_ascii_uppercase() { BLACK } else { WHITE };
+      let n = if count.is_empty() { 1 } else {
+         count.parse().map_err(|_| format!("SFEN hand count {count} is too many"))?
+      };
+      board.give(&color, piece, n);
+      count.clear();
+   }
+   if count.is_empty() {
+      Ok(())
+   } else {
+      Err(format!("SFEN hand {hands} ends in a count"))
+   }
+}
+
+// ----- TESTS -------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   const HIRATE: &str = "lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL b - 1";
+
+   #[test]
+   fn test_hirate_sfen() -> ErrStr<()> {
+      let setup = from_sfen(HIRATE)?;
+      assert_eq!(Board::default(), setup.board);
+      assert_eq!(1, setup.first);
+      Ok(())
+   }
+
+   #[test]
+   fn test_handicaps() -> ErrStr<()> {
+      let bishop = handicap("Bishop")?;
+      assert_eq!(WHITE, bishop.board.turn);
+      assert_eq!(39, bishop.board.occupied().len());
+      assert!(bishop.board.at((2, 2)).is_none());
+      assert_eq!(PresetKA, handicap("ka")?.preset);
+      assert_eq!(30, handicap("Ten pieces")?.board.occupied().len());
+      assert_eq!(Some("Rook and lance"), handicap_name(PresetHIKY));
+      assert!(handicap("Queen").is_err());
+      Ok(())
+   }
+
+   #[test]
+   fn test_midgame_sfen() -> ErrStr<()> {
+      let setup = from_sfen("8k/9/7+P1/9/9/9/9/9/K8 w 2Gp 121")?;
+      assert_eq!(WHITE, setup.board.turn);
+      assert_eq!(121, setup.first);
+      let tokin = setup.board.at((2, 3)).ok_or("No piece on 2c")?;
+      assert_eq!((BLACK, true), (tokin.color.clone(), tokin.piece.promoted));
+      assert_eq!((2, 1), (setup.board.in_hand(&BLACK, GOLD), setup.board.in_hand(&WHITE, PAWN)));
+      Ok(())
+   }
+
+   #[test]
+   fn test_to_sfen() -> ErrStr<()> {
+      assert_eq!(HIRATE, to_sfen(&Board::default()));
+      let midgame = "8k/9/7+P1/9/9/9/9/9/K8 w 2Gp 1";
+      assert_eq!(midgame, to_sfen(&from_sfen(midgame)?.board));
+      Ok(())
+   }
+
+   #[test]
+   fn test_bad_sfens() {
+      assert!(from_sfen("9/9/9 b -").is_err());
+      assert!(from_sfen("lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSN b -").is_err());
+      assert!(from_sfen(&HIRATE.replace(" b ", " x ")).is_err());
+      assert!(from_sfen(&HIRATE.replace(" - ", " 2K ")).is_err());
+   }
+}
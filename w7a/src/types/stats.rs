@@ -0,0 +1,225 @@
+use std::str::FromStr;
+
+use chrono::Duration;
+
+use book::err_utils::ErrStr;
+
+use super::{
+   clock::{Think, byoyomi_from, thinks, time_control},
+   w7a::{Color::{self, *}, W7A},
+   writer::clock
+};
+
+// What the clocks say of a game: each side's longest thinks, its thinking
+// time in the opening, the middlegame and the endgame, and the move it
+// entered byoyomi on, as a table; or the thinks, move by move, as CSV; or
+// the cumulative clocks of Black and White, against the move, as an SVG chart.
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum Report { TABLE, CSV, SVG }
+
+impl FromStr for Report {
+   type Err = String;
+   fn from_str(s: &str) -> ErrStr<Self> {
+      match s.to_ascii_lowercase().as_str() {
+         "table" => Ok(Report::TABLE),
+         "csv" => Ok(Report::CSV),
+         "svg" => Ok(Report::SVG),
+         _ => Err(format!("Unknown report {s}; choose table, csv or svg"))
+      }
+   }
+}
+
+/// The phases of a game, by move number: the last move of each
+pub const PHASES: [(&str, usize); 3] =
+   [("opening", 40), ("middlegame", 80), ("endgame", usize::MAX)];
+
+/// How many of a side's longest thinks the table lists
+pub const LONGEST: usize = 5;
+
+pub struct Stats {
+   pub thinks: Vec<Think>,
+   pub longest: [Vec<Think>; 2],
+   pub phases: [[Duration; 3]; 2],
+   pub byoyomi: [Option<usize>; 2]
+}
+
+fn index(side: &Color) -> usize { if *side == BLACK { 0 } else { 1 } }
+
+pub fn stats(game: &W7A) -> ErrStr<Stats> {
+   let control = time_control(game)?;
+   let thinks = thinks(game, control.as_ref());
+   let mut longest = [Vec::new(), Vec::new()];
+   let mut phases = [[Duration::zero(); 3]; 2];
+   for think in &thinks {
+      let phase = PHASES.iter().position(|(_, last)| think.n <= *last).unwrap_or(2);
+      phases[index(&think.color)][phase] += think.think;
+      longest[index(&think.color)].push(think.clone());
+   }
+   for side in longest.iter_mut() {
+      // the longest first; of equal thinks, the earliest
+      side.sort_by(|a, b| b.think.cmp(&a.think).then(a.n.cmp(&b.n)));
+      side.truncate(LONGEST);
+   }
+   let byoyomi = [byoyomi_from(&thinks, &BLACK), byoyomi_from(&thinks, &WHITE)];
+   Ok(Stats { thinks, longest, phases, byoyomi })
+}
+
+pub fn report(game: &W7A, report: Report) -> ErrStr<String> {
+   let stats = stats(game)?;
+   Ok(match report {
+      Report::TABLE => table(game, &stats),
+      Report::CSV => csv(&stats),
+      Report::SVG => svg(&stats)
+   })
+}
+
+// ----- TABLE -------------------------------------------------------
+
+const SIDES: [&str; 2] = ["Black", "White"];
+
+fn table(game: &W7A, stats: &Stats) -> String {
+   let mut lines = Vec::new();
+   let names: Vec<String> = SIDES.iter().map(|side| {
+      game.tag(side).map_or(side.to_string(), |name| format!("{name} ({side})"))
+   }).collect();
+   lines.push(names.join(" v. "));
+   lines.push(String::new());
+   lines.push("Longest thinks".to_string());
+   for (side, thinks) in SIDES.iter().zip(&stats.longest) {
+      let moves: Vec<String> =
+         thinks.iter().map(|t| format!("{}. {}", t.n, clock(t.think))).collect();
+      lines.push(format!("  {side}  {}", moves.join(", ")));
+   }
+   lines.push(String::new());
+   lines.push(format!("Thinking time{:>15}{:>10}", SIDES[0], SIDES[1]));
+   for (i, (phase, _)) in PHASES.iter().enumerate() {
+      lines.push(format!("  {phase:<18}{}  {}", clock(stats.phases[0][i]),
+                         clock(stats.phases[1][i])));
+   }
+   let total = |side: usize| {
+      stats.phases[side].iter().fold(Duration::zero(), |a, d| a + *d)
+   };
+   lines.push(format!("  {:<18}{}  {}", "total", clock(total(0)), clock(total(1))));
+   lines.push(String::new());
+   lines.push("Byoyomi".to_string());
+   for (side, from) in SIDES.iter().zip(stats.byoyomi) {
+      let from = from.map_or("never".to_string(), |n| format!("from move {n}"));
+      lines.push(format!("  {side}  {from}"));
+   }
+   lines.join("\n")
+}
+
+// ----- CSV -------------------------------------------------------
+
+fn csv(stats: &Stats) -> String {
+   let mut totals = [Duration::zero(), Duration::zero()];
+   let mut lines = vec!["move,side,think,black,white,byoyomi".to_string()];
+   for think in &stats.thinks {
+      totals[index(&think.color)] = think.total;
+      lines.push(format!("{},{},{},{},{},{}", think.n, SIDES[index(&think.color)],
+                         clock(think.think), clock(totals[0]), clock(totals[1]),
+                         think.byoyomi));
+   }
+   lines.join("\n")
+}
+
+// ----- SVG -------------------------------------------------------
+
+const WIDTH: usize = 640;
+const HEIGHT: usize = 400;
+const MARGIN: usize = 40;
+const COLORS: [&str; 2] = ["black", "firebrick"];
+
+// cumulative time, in hours, up the page, against the move, across it
+fn svg(stats: &Stats) -> String {
+   let moves = stats.thinks.last().map_or(1, |t| t.n).max(1);
+   let hours = stats.thinks.iter().map(|t| t.total.num_seconds()).max()
+                    .map_or(1, |secs| (secs + 3599) / 3600).max(1);
+   let (w, h) = ((WIDTH - 2 * MARGIN) as f64, (HEIGHT - 2 * MARGIN) as f64);
+   let x = |n: usize| MARGIN as f64 + w * n as f64 / moves as f64;
+   let y = |d: Duration| {
+      (HEIGHT - MARGIN) as f64 - h * d.num_seconds() as f64 / (hours * 3600) as f64
+   };
+   let mut svg = vec![
+      format!("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{WIDTH}\" \
+               height=\"{HEIGHT}\" font-family=\"sans-serif\" font-size=\"12\">"),
+      format!("<line x1=\"{MARGIN}\" y1=\"{0}\" x2=\"{1}\" y2=\"{0}\" \
+               stroke=\"gray\"/>", HEIGHT - MARGIN, WIDTH - MARGIN),
+      format!("<line x1=\"{MARGIN}\" y1=\"{MARGIN}\" x2=\"{MARGIN}\" y2=\"{}\" \
+               stroke=\"gray\"/>", HEIGHT - MARGIN),
+      format!("<text x=\"{}\" y=\"{}\" text-anchor=\"middle\">move {moves}</text>",
+              WIDTH - MARGIN, HEIGHT - MARGIN / 4),
+      format!("<text x=\"{}\" y=\"{}\" text-anchor=\"middle\">{hours}h</text>",
+              MARGIN / 2, MARGIN + 4)
+   ];
+   for (side, name) in SIDES.iter().enumerate() {
+      let mut points = vec![format!("{:.1},{:.1}", x(0), y(Duration::zero()))];
+      points.extend(stats.thinks.iter()
+                         .filter(|t| index(&t.color) == side)
+                         .map(|t| format!("{:.1},{:.1}", x(t.n), y(t.total))));
+      svg.push(format!("<polyline fill=\"none\" stroke=\"{}\" stroke-width=\"2\" \
+                        points=\"{}\"/>", COLORS[side], points.join(" ")));
+      svg.push(format!("<text x=\"{}\" y=\"{}\" fill=\"{}\">{name}</text>",
+                       MARGIN + 10, MARGIN + 15 * (side + 1), COLORS[side]));
+   }
+   svg.push("</svg>".to_string());
+   svg.join("\n")
+}
+
+// ----- TESTS -------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   use crate::types::w7a::load_w7a;
+
+   fn oi_stats() -> ErrStr<(W7A, Stats)> {
+      let dir = "../data/game_records/reijer_grimberger";
+      let game = load_w7a(&format!("{dir}/2013-07-11-54th-oi-sen-game-1.w7a"))?;
+      let stats = stats(&game)?;
+      Ok((game, stats))
+   }
+
+   #[test]
+   fn test_phases_add_up() -> ErrStr<()> {
+      let (game, stats) = oi_stats()?;
+      let last = game.moves.len() - 1;
+      for side in 0..2 {
+         let total = stats.phases[side].iter().fold(Duration::zero(), |a, d| a + *d);
+         let own = if index(&game.moves[last].color) == side { last } else { last - 1 };
+         assert_eq!(game.moves[own].total_time, total);
+      }
+      assert_eq!([None, None], stats.byoyomi);
+      Ok(())
+   }
+
+   #[test]
+   fn test_longest_first() -> ErrStr<()> {
+      let (_, stats) = oi_stats()?;
+      for side in &stats.longest {
+         assert_eq!(LONGEST, side.len());
+         assert!(side.windows(2).all(|w| w[0].think >= w[1].think));
+      }
+      Ok(())
+   }
+
+   #[test]
+   fn test_reports() -> ErrStr<()> {
+      let game: W7A = "[TimeControl \"10m+30s\"]\n\
+                       1.P7g-7f     00:09:00  00:00:00\n\
+                       2.P3c-3d     00:09:00  00:01:00\n\
+                       3.P2g-2f     00:10:20  00:01:00\n".parse()?;
+      let table = report(&game, Report::TABLE)?;
+      assert!(table.contains("  Black  1. 00:09:00, 3. 00:01:20"), "{table}");
+      assert!(table.contains("  Black  from move 3\n  White  never"), "{table}");
+      let csv = report(&game, Report::CSV)?;
+      assert_eq!(Some("3,Black,00:01:20,00:10:20,00:01:00,true"), csv.lines().last());
+      let svg = report(&game, Report::SVG)?;
+      assert!(svg.starts_with("<svg") && svg.ends_with("</svg>"));
+      assert_eq!(2, svg.matches("<polyline").count());
+      assert!("pdf".parse::<Report>().is_err());
+      Ok(())
+   }
+}
@@ -0,0 +1,189 @@
+use std::collections::HashMap;
+
+use book::err_utils::ErrStr;
+
+use super::{
+   board::{Board, opponent},
+   check::gives,
+   setup::setup,
+   w7a::{Color::{self, *}, Move, W7A},
+   writer::notation
+};
+
+// A tsume (mate-in-N) search, to put an annotator's "a mating threat", or
+// "not leading to mate", to the test. The attacker checks on every move; the
+// defender answers with any legal move. The search deepens a ply pair at a
+// time, so the mate it finds is the shortest, and along it the defence holds
+// out longest; it is depth-first and, the moves being generated in a fixed
+// order, finds the same mate run after run.
+
+/// Plies searched when no limit is given: mate in 7
+pub const PLIES: usize = 7;
+
+struct Search {
+   // positions, by hash, with no mate in this many plies, or fewer
+   refuted: HashMap<u64, usize>
+}
+
+impl Search {
+   fn new() -> Self { Search { refuted: HashMap::new() } }
+
+   // the shortest mate, of at most `plies`, the side to move forces
+   fn mate(&mut self, board: &Board, plies: usize) -> Option<Vec<Move>> {
+      (1..=plies).step_by(2).find_map(|depth| self.attack(board, depth))
+   }
+
+   // a mate in `depth` plies (odd), checking with every move
+   fn attack(&mut self, board: &Board, depth: usize) -> Option<Vec<Move>> {
+      let hash = board.hash();
+      if self.refuted.get(&hash).is_some_and(|d| *d >= depth) { return None; }
+      for mov in board.legal_moves() {
+         let mut after = board.clone();
+         if after.advance(&mov).is_err() || !after.in_check(&after.turn) { continue; }
+         let rest = if after.is_mate() {
+            Some(Vec::new())
+         } else if depth >= 3 {
+            self.defend(&after, depth - 1)
+         } else {
+            None
+         };
+         if let Some(rest) = rest {
+            return Some([vec![mov], rest].concat());
+         }
+      }
+      self.refuted.insert(hash, depth);
+      None
+   }
+
+   // every reply is mated within `depth` plies (even); the longest line
+   fn defend(&mut self, board: &Board, depth: usize) -> Option<Vec<Move>> {
+      let mut longest: Option<Vec<Move>> = None;
+      for mov in board.legal_moves() {
+         let mut after = board.clone();
+         if after.advance(&mov).is_err() { continue; }
+         let rest = self.mate(&after, depth - 1)?;
+         if longest.as_ref().is_none_or(|line| rest.len() + 1 > line.len()) {
+            longest = Some([vec![mov], rest].concat());
+         }
+      }
+      longest
+   }
+}
+
+/// The shortest mate, in at most `plies` plies, the side to move can force,
+/// giving check with every move: its moves, unnumbered, the mate last
+pub fn mate(board: &Board, plies: usize) -> Option<Vec<Move>> {
+   Search::new().mate(board, plies)
+}
+
+/// The position after move n of the game; its setup, for 0
+pub fn board_after(game: &W7A, n: usize) -> ErrStr<Board> {
+   let mut board = setup(&game.header)?.board;
+   if n > 0 && !game.moves.iter().any(|m| m.n == n) {
+      return Err(format!("No move {n} in the game"));
+   }
+   for mov in game.moves.iter().take_while(|m| m.n <= n) {
+      board.apply(mov)?;
+   }
+   Ok(board)
+}
+
+/// Whether the side to move, after move n, mates in at most `plies` plies;
+/// or, with `threat`, whether the side that played move n would, were its
+/// opponent to pass. The mate is written in w7a notation, numbered on from n.
+pub fn report(game: &W7A, n: usize, plies: usize, threat: bool) -> ErrStr<String> {
+   let mut board = board_after(game, n)?;
+   let mut first = n + 1;
+   let mut pass = String::new();
+   if threat {
+      if board.in_check(&board.turn) {
+         return Err(format!("After move {n} the side to move is in check, and \
+                             cannot pass"));
+      }
+      pass = format!("After a pass by {}, ", side(&board.turn));
+      board.turn = opponent(&board.turn);
+      first += 1;
+   }
+   let attacker = side(&board.turn);
+   Ok(match mate(&board, plies) {
+      None => format!("{pass}{attacker} has no mate in {}, checking on every move",
+                      in_plies(plies)),
+      Some(line) => {
+         let plies = line.len();
+         let mut moves = Vec::new();
+         for (i, mov) in line.iter().enumerate() {
+            board.apply(mov)?;
+            let mark = gives(&board, i + 1 == plies).map_or("", |c| c.suffix());
+            moves.push(format!("{}.{}{mark}", first + i, notation(mov)));
+         }
+         format!("{pass}{attacker} mates in {}: {}", in_plies(plies), moves.join(" "))
+      }
+   })
+}
+
+fn in_plies(n: usize) -> String { format!("{n} {}", if n == 1 { "ply" } else { "plies" }) }
+
+fn side(c: &Color) -> &'static str { if *c == BLACK { "Black" } else { "White" } }
+
+// ----- TESTS -------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   use crate::types::setup::from_sfen;
+
+   fn board(sfen: &str) -> ErrStr<Board> { Ok(from_sfen(sfen)?.board) }
+
+   // the line mates: each move checks, and the last leaves no way out
+   fn mates(board: &Board, line: &[Move]) -> ErrStr<bool> {
+      let mut board = board.clone();
+      for (i, mov) in line.iter().enumerate() {
+         board.apply(mov)?;
+         if i % 2 == 0 && !board.in_check(&board.turn) { return Ok(false); }
+      }
+      Ok(board.is_mate())
+   }
+
+   #[test]
+   fn test_mate_in_one() -> ErrStr<()> {
+      let head = board("4k4/9/4P4/9/9/9/9/9/9 b G 1")?;
+      let line = mate(&head, 1).ok_or("No mate")?;
+      assert_eq!(1, line.len());
+      assert_eq!("G*5b", notation(&line[0]));
+      Ok(())
+   }
+
+   #[test]
+   fn test_mate_in_three() -> ErrStr<()> {
+      // the king in the corner: the rook drives it out, the silver mates
+      let corner = board("8k/9/8P/9/9/9/9/9/9 b RS 1")?;
+      assert_eq!(None, mate(&corner, 1));
+      let line = mate(&corner, PLIES).ok_or("No mate")?;
+      let moves: Vec<String> = line.iter().map(notation).collect();
+      assert_eq!(vec!["R*1b", "K1a-2a", "S*3b"], moves);
+      assert!(mates(&corner, &line)?);
+      Ok(())
+   }
+
+   #[test]
+   fn test_no_pawn_drop_mate() -> ErrStr<()> {
+      // P*1b would mate, but a pawn dropped may not
+      let hemmed = board("7nk/7l1/8G/9/9/9/9/9/9 b P 1")?;
+      assert_eq!(None, mate(&hemmed, 1));
+      Ok(())
+   }
+
+   #[test]
+   fn test_report() -> ErrStr<()> {
+      // White's pawn, on 5g, backs a gold dropped on Black's king's head
+      let game: W7A = "[SFEN \"4k4/9/9/9/9/9/4p4/9/4K4 w 2g 1\"]\n\
+                       1.G*9a       00:00:00  00:00:00\n".parse()?;
+      assert_eq!("Black has no mate in 7 plies, checking on every move",
+                 report(&game, 1, PLIES, false)?);
+      assert_eq!("After a pass by Black, White mates in 1 ply: 3.G*5h#",
+                 report(&game, 1, PLIES, true)?);
+      assert!(report(&game, 2, PLIES, false).is_err());
+      Ok(())
+   }
+}
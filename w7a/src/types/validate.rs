@@ -0,0 +1,154 @@
+use serde::Serialize;
+
+use super::{
+   check::lint_checks,
+   clock::lint_clocks,
+   cst::Cst,
+   error::{ErrorKind::*, W7AErr},
+   formats::to_jkf,
+   jishogi::lint_jishogi,
+   repetition::lint_repetition,
+   w7a::{W7A, load_cst}
+};
+
+// Validating reads a record and replays it, converting nothing. It reports
+// everything a recovering read finds (bad moves, bad clocks, moves numbered
+// out of turn, lines after the result), the first illegal move of the replay
+// (a piece that cannot get there, a second pawn on a file, a move that leaves
+// its own king in check, ...), clocks that cannot be right, a result the
+// replay contradicts (a sennichite without the repetition, a jishogi short of
+// the points, a mate with a way out), a check or mate marked on a move that
+// gives none, and the header tags a record should have and does not.
+
+// The diagnostics are W7AErrs, so they print rustc-style; for the pre-commit
+// hook and the editors they are also written as a JSON array:
+
+// [{"file":"games/54oi1.w7a","line":3,"column":3,"severity":"error",
+//   "code":"w7a::bad-move","message":"No square 4z on the board"}]
+
+/// The header tags every record has
+pub const REQUIRED: [&str; 4] = ["Black", "White", "Event", "Date"];
+
+/// The diagnostics of a record, file-wide ones first, then by line
+pub fn validate(cst: &Cst) -> Vec<W7AErr> {
+   let (game, mut found) = W7A::recover(cst);
+   // a replay after a move was dropped, or misnumbered, is meaningless
+   if found.iter().all(|err| !err.is_error()) {
+      found.extend(to_jkf(&game).err().map(|err| err.locate(cst)));
+      found.extend(lint_clocks(cst, &game));
+      found.extend(lint_repetition(cst, &game));
+      found.extend(lint_jishogi(cst, &game));
+      found.extend(lint_checks(cst, &game));
+   }
+   found.extend(REQUIRED.iter().filter(|tag| game.tag(tag).is_none()).map(|tag| {
+      W7AErr::new(MISSING, &format!("No {tag} header")).warning().locate(cst)
+   }));
+   found.sort_by_key(|err| err.line().unwrap_or(0));
+   found
+}
+
+/// The diagnostics of the file; one that cannot be read is one diagnostic
+pub fn validate_file(filename: &str) -> Vec<W7AErr> {
+   match load_cst(filename) {
+      Ok(cst) => validate(&cst),
+      Err(err) => vec![err]
+   }
+}
+
+#[derive(Serialize)]
+struct Diagnostic<'a> {
+   file: Option<&'a str>,
+   line: Option<usize>,
+   column: Option<usize>,
+   severity: String,
+   code: &'static str,
+   message: &'a str
+}
+
+/// The diagnostics as a JSON array
+pub fn diagnostics_json(found: &[W7AErr]) -> String {
+   let diagnostics: Vec<Diagnostic> = found.iter().map(|err| Diagnostic {
+      file: err.file(),
+      line: err.line(),
+      column: err.column(),
+      severity: err.severity.to_string(),
+      code: err.kind.code(),
+      message: &err.message
+   }).collect();
+   // plain strings and numbers, so serializing cannot fail
+   serde_json::to_string(&diagnostics).unwrap_or_else(|_| "[]".to_string())
+}
+
+// ----- TESTS -------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   use crate::types::error::ErrorKind;
+
+   const HEADER: &str = "[Black \"Habu\"]\n\
+                         [White \"Namekata\"]\n\
+                         [Event \"54th Oi-sen, Game 1\"]\n\
+                         [Date \"July 10th 2013\"]\n";
+
+   fn kinds(found: &[W7AErr]) -> Vec<(ErrorKind, Option<usize>)> {
+      found.iter().map(|err| (err.kind, err.line())).collect()
+   }
+
+   #[test]
+   fn test_valid_record() {
+      let dir = "../data/game_records/reijer_grimberger";
+      let found = validate_file(&format!("{dir}/2013-07-11-54th-oi-sen-game-1.w7a"));
+      assert!(found.iter().all(|err| !err.is_error()), "{found:?}");
+   }
+
+   #[test]
+   fn test_numbering() {
+      let source = format!("{HEADER}1.P7g-7f     00:00:00  00:00:00\n\
+                                     3.P3c-3d     00:00:00  00:00:00\n\
+                                     3.P2g-2f     00:00:00  00:00:00\n");
+      let found = validate(&Cst::parse(source));
+      assert_eq!(vec![(SEQUENCE, Some(6)), (SEQUENCE, Some(7))], kinds(&found));
+      assert_eq!("Move 3 follows move 1: skipped 2", found[0].message);
+   }
+
+   #[test]
+   fn test_illegal_and_missing() {
+      let source = "[Black \"Habu\"]\n\
+                    1.P7g-7f     00:00:00  00:00:00\n\
+                    2.P7g-7f     00:00:00  00:00:00\n";
+      let found = validate(&Cst::parse(source));
+      assert_eq!(vec![(MISSING, None), (MISSING, None), (MISSING, None),
+                      (ILLEGAL, Some(3))], kinds(&found));
+   }
+
+   #[test]
+   fn test_nifu() {
+      let source = format!("{HEADER}[SFEN \"4k4/9/9/9/9/9/4P4/9/4K4 b P 1\"]\n\
+                                     1.P*5e       00:00:00  00:00:00\n");
+      let found = validate(&Cst::parse(source));
+      assert_eq!(vec![(ILLEGAL, Some(6))], kinds(&found));
+      assert_eq!("Move 1: a second pawn on file 5 (nifu)", found[0].message);
+   }
+
+   #[test]
+   fn test_self_check() {
+      // the gold, pinned to its king by the rook, may not leave the file
+      let source = format!("{HEADER}[SFEN \"8k/4r4/9/9/9/9/9/4G4/4K4 b - 1\"]\n\
+                                     1.G5h-4h     00:00:00  00:00:00\n");
+      let found = validate(&Cst::parse(source));
+      assert_eq!(vec![(ILLEGAL, Some(6))], kinds(&found));
+      assert_eq!("Move 1: leaves the mover's king in check", found[0].message);
+   }
+
+   #[test]
+   fn test_json() {
+      let source = format!("{HEADER}1.P7g-7z     00:00:00  00:00:00\n");
+      let json = diagnostics_json(&validate(&Cst::parse(source).named("54oi1.w7a")));
+      assert_eq!("[{\"file\":\"54oi1.w7a\",\"line\":5,\"column\":3,\
+                  \"severity\":\"error\",\"code\":\"w7a::bad-move\",\
+                  \"message\":\"No square 7z on the board\"}]", json);
+      assert_eq!("[]", diagnostics_json(&[]));
+   }
+}
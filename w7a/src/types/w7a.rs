@@ -1,59 +1,266 @@
+use std::{
+   fmt, fs,
+   io::{BufRead, Read},
+   str::FromStr
+};
+
 use chrono::Duration;
 
 use book::{
-   file_utils::lines_from_file,
-   err_utils::ErrStr,
-   list_utils::ht,
-   string_utils::to_string,
-   compose
+   err_utils::{ErrStr,err_or},
+   list_utils::ht
 };
 
-use super::utils::Lookup;
+use super::{
+   board::opponent,
+   check::marked,
+   cst::{Cst, Line, LineKind::*, TokenKind::*},
+   error::{ErrW7A, ErrorKind::*, W7AErr},
+   setup::setup,
+   utils::Lookup
+};
 
-trait Scanner {
-   fn ingest(lines: &[String]) -> ErrStr<(Self, Vec<String>)>
+/// Scans its part of the record from the lines, returning the lines left
+/// over: a slice of the lines given
+pub trait Scanner {
+   fn ingest<S: AsRef<str>>(lines: &[S]) -> ErrStr<(Self, &[S])>
       where Self: Sized;
 }
 
+#[derive(Debug,Clone,PartialEq)]
 pub struct W7A {
-   header: Header,
-   game_comment: GameComment
-   // moves: Vec<Move>
+   pub header: Header,
+   pub game_comment: GameComment,
+   pub moves: Vec<Move>,
+   pub outcome: Option<Outcome>,
+   pub sealed: Vec<usize>  // the moves sealed, each ending a session of play
 }
 
+// The game is built from the concrete syntax tree, line by line: the tags
+// make the header, and the comment lines and blank lines belong to what
+// came before them: the game, a move, or the result.
+
 impl Scanner for W7A {
-   fn ingest(lines: &[String]) -> ErrStr<(Self, Vec<String>)> {
-      let (header, rest) = Header::ingest(lines)?;
-      let (game_comment, tail) = GameComment::ingest(&rest)?;
-      Ok((W7A { header, game_comment }, tail))
+   fn ingest<S: AsRef<str>>(lines: &[S]) -> ErrStr<(Self, &[S])> {
+      let end = game_end(lines);
+      let game = scan_w7a(&lines[..end])?;
+      Ok((game, &lines[end..]))
+   }
+}
+
+impl FromStr for W7A {
+   type Err = W7AErr;
+   fn from_str(source: &str) -> ErrW7A<Self> {
+      parse_w7a(source)
+   }
+}
+
+impl W7A {
+   /// The value of the header tag, e.g. "Black"
+   pub fn tag(&self, name: &str) -> Option<&str> {
+      self.header.header.get(name).map(String::as_str)
+   }
+
+   pub fn tags(&self) -> &Lookup { &self.header.header }
+
+   /// The comment between the header and the first move
+   pub fn comment(&self) -> Option<&str> { self.game_comment.comment.as_deref() }
+
+   pub fn moves(&self) -> &[Move] { &self.moves }
+
+   pub fn outcome(&self) -> Option<&Outcome> { self.outcome.as_ref() }
+
+   pub fn from_cst(cst: &Cst) -> ErrW7A<W7A> {
+      let (game, _warnings) = Reader { cst, recover: false, found: Vec::new() }.read()?;
+      Ok(game)
+   }
+
+   /// The game, as much of it as could be read, and everything wrong with it
+   pub fn recover(cst: &Cst) -> (W7A, Vec<W7AErr>) {
+      let reader = Reader { cst, recover: true, found: Vec::new() };
+      match reader.read() {
+         Ok(recovered) => recovered,
+         Err(err) => (W7A::empty(), vec![err]) // a recovering read does not fail
+      }
+   }
+
+   fn empty() -> W7A {
+      W7A {
+         header: Header { header: Lookup::new() },
+         game_comment: GameComment { comment: None },
+         moves: Vec::new(),
+         outcome: None,
+         sealed: Vec::new()
+      }
+   }
+
+   // the clocks, Black's and White's, as of the last move
+   fn clocks(&self) -> (Duration, Duration) {
+      self.moves.last().map_or((Duration::zero(), Duration::zero()), |m| {
+         if color(m) == BLACK { (m.total_time, m.other_time) }
+         else { (m.other_time, m.total_time) }
+      })
+   }
+
+   // the comment lines so far go to the latest of the game, move or result
+   fn attach(&mut self, lines: &mut Vec<&str>) {
+      let comment = paragraphs(lines);
+      lines.clear();
+      if let Some(outcome) = self.outcome.as_mut() {
+         outcome.comment = comment;
+      } else if let Some(mov) = self.moves.last_mut() {
+         mov.comment = comment;
+      } else {
+         self.game_comment.comment = comment;
+      }
+   }
+}
+
+// ----- READER -------------------------------------------------------
+
+// A strict read stops at the first error. A recovering read notes the error
+// and goes on: a bad tag or move is skipped, a bad clock is taken to be the
+// clock as of the move before; it warns, too, of what a strict read lets pass.
+
+struct Reader<'a> {
+   cst: &'a Cst<'a>,
+   recover: bool,
+   found: Vec<W7AErr>  // the errors and warnings, when recovering
+}
+
+impl<'a> Reader<'a> {
+   fn read(mut self) -> ErrW7A<(W7A, Vec<W7AErr>)> {
+      let cst = self.cst;
+      let mut game = W7A::empty();
+      let mut comment: Vec<&str> = Vec::new();
+      let mut turn: Option<(Color, usize)> = None; // the side to move, and its move
+      for line in cst.lines() {
+         let text = cst.text(line.span);
+         match line.kind {
+            TAG => {
+               if let Some((key, value)) = self.check(tag_of(cst, line))? {
+                  if game.header.header.contains_key(&key) {
+                     self.warn(W7AErr::new(HEADER, &format!("[{key}] is given \
+                                  twice; the later value is kept"))
+                                      .at(cst, line.span));
+                  }
+                  game.header.header.insert(key, value);
+               }
+            },
+            BLANK | COMMENT => comment.push(text),
+            MOVE | SEALED | RESULT if game.outcome.is_some() => {
+               let late = W7AErr::new(SEQUENCE, "The game is over: this line \
+                                                 comes after the result")
+                             .at(cst, line.span);
+               if !self.recover { return Err(late); }
+               self.warn(late);
+            },
+            MOVE => {
+               game.attach(&mut comment);
+               let (mover, expected) = match turn.take() {
+                  Some(turn) => turn,
+                  None => self.opening(&game)?
+               };
+               let mut next = expected + 1;
+               if let Some(mut mov) = self.check(motion_of(cst, line, mover.clone()))? {
+                  let first = game.moves.is_empty();
+                  self.check(numbered(cst, line, mov.n, expected, first))?;
+                  let (black, white) = self.clocks(line, Some(mov.n), game.clocks())?;
+                  (mov.total_time, mov.other_time) =
+                     if mover == BLACK { (black, white) } else { (white, black) };
+                  next = mov.n + 1;
+                  game.moves.push(mov);
+               }
+               turn = Some((opponent(&mover), next));
+            },
+            SEALED => match game.moves.last() {
+               Some(mov) => game.sealed.push(mov.n),
+               None => {
+                  self.check::<()>(Err(W7AErr::new(SEQUENCE, "No move to seal")
+                                                 .at(cst, line.span)))?;
+               }
+            },
+            RESULT => {
+               game.attach(&mut comment);
+               if let Some(ending) = self.check(ending_of(cst, line))? {
+                  let (black_time, white_time) =
+                     self.clocks(line, None, game.clocks())?;
+                  game.outcome = Some(Outcome { ending, black_time, white_time,
+                                                comment: None });
+               }
+            }
+         }
+      }
+      game.attach(&mut comment);
+      if turn.is_none() { self.opening(&game)?; }
+      if let Some(sealed) = game.tag("Sealed").map(sealed_of) {
+         let sealed = sealed.map_err(|msg| {
+            W7AErr::new(HEADER, &msg).on_tag("Sealed").locate(cst)
+         });
+         game.sealed.extend(self.check(sealed)?.unwrap_or_default());
+      }
+      game.sealed.sort();
+      game.sealed.dedup();
+      Ok((game, self.found))
+   }
+
+   // Ok(None) is an error noted, the line to be skipped
+   fn check<T>(&mut self, res: ErrW7A<T>) -> ErrW7A<Option<T>> {
+      match res {
+         Ok(t) => Ok(Some(t)),
+         Err(err) if self.recover => { self.found.push(err); Ok(None) },
+         Err(err) => Err(err)
+      }
+   }
+
+   // the side to move first, and the number of its move, as the header sets up
+   // the game; recovering from a bad setup, Black and move 1
+   fn opening(&mut self, game: &W7A) -> ErrW7A<(Color, usize)> {
+      let tag = if game.tag("SFEN").is_some() { "SFEN" } else { "Handicap" };
+      let setup = setup(&game.header).map_err(|msg| {
+         W7AErr::new(HEADER, &msg).on_tag(tag).locate(self.cst)
+      });
+      Ok(self.check(setup)?.map_or((BLACK, 1), |setup| (setup.board.turn, setup.first)))
+   }
+
+   fn warn(&mut self, warning: W7AErr) {
+      if self.recover { self.found.push(warning.warning()); }
+   }
+
+   // the clocks of the line, or, recovering, the clocks as they were
+   fn clocks(&mut self, line: &Line, n: Option<usize>,
+             before: (Duration, Duration)) -> ErrW7A<(Duration, Duration)> {
+      Ok(self.check(clocks_of(self.cst, line, n))?.unwrap_or(before))
    }
 }
 
 // ----- HEADER -------------------------------------------------------
 
+#[derive(Debug,Clone,PartialEq)]
 pub struct Header {
    pub header: Lookup
 }
 
 impl Scanner for Header {
-   fn ingest(lines: &[String]) -> ErrStr<(Self, Vec<String>)> {
+   fn ingest<S: AsRef<str>>(lines: &[S]) -> ErrStr<(Self, &[S])> {
       ingest_header(lines)
    }
 }
 
 // ----- COMMENTS -------------------------------------------------------
 
-type Comment = Option<String>;
+pub type Comment = Option<String>;
 
 /// The GameComment is special:
 
 /// 1. There's only one of them (if present)
 /// 2. it occurs between the Header and the first Move
 
-pub struct GameComment { comment: Comment } 
+#[derive(Debug,Clone,PartialEq)]
+pub struct GameComment { pub comment: Comment } 
 
 impl Scanner for GameComment {
-   fn ingest(lines: &[String]) -> ErrStr<(Self, Vec<String>)> {
+   fn ingest<S: AsRef<str>>(lines: &[S]) -> ErrStr<(Self, &[S])> {
       // From here to the line starting with "1." is either the GameComment
       // or a set of empty lines, which we ignore
       let (comment, rest) = collect_comment(lines)?;
@@ -64,30 +271,23 @@ impl Scanner for GameComment {
 // ----- MOVE -------------------------------------------------------
 
 // e.g.: 1.P7g-7f     00:00:00  00:00:00
+#[derive(Debug,Clone,PartialEq)]
 pub struct Move {
-   n: usize,
-   piece: Piece,
-   from: Option<Position>, // None meaning from hand
-   to: Position,
-   capture: bool,
-   drop: bool,
-   total_time: Duration,
-   comment: Comment
+   pub n: usize,
+   pub color: Color,           // the side to move, as the game was read
+   pub piece: Piece,
+   pub from: Option<Position>, // None meaning from hand
+   pub to: Position,
+   pub capture: bool,
+   pub drop: bool,
+   pub promote: Option<bool>,  // '+' promotes, '=' declines, else None
+   pub total_time: Duration,
+   pub other_time: Duration,   // the clock of the side not moving
+   pub comment: Comment
 }
 
-/*
-impl Scanner for Move {
-   fn ingest(lines: &[String]) -> ErrStr<(Self, Vec<String>)> {
-      // xxx
-   }
-}
-*/
-
 pub fn dur(a: Option<&Move>, b: &Move) -> Duration {
-   let start =
-      a.and_then(|m| Some(m.total_time))
-       .or(Some(Duration::zero()))
-       .unwrap();
+   let start = a.map_or(Duration::zero(), |m| m.total_time);
    b.total_time - start
 }
 
@@ -98,27 +298,97 @@ pub enum Color { BLACK, WHITE }
 
 use Color::*;
 
-pub fn color(m: &Move) -> Color {
-   if m.n % 2 == 1 { BLACK } else { WHITE }
+/// The side that played the move: the side to move as the game was read,
+/// from the setup on, not the parity of its number
+pub fn color(m: &Move) -> Color { m.color.clone() }
+
+// ----- OUTCOME -------------------------------------------------------
+
+// e.g.: Resigns      07:55:00  07:59:00
+
+#[derive(Debug,Clone,PartialEq)]
+pub enum Ending { RESIGNS, SENNICHITE, JISHOGI, TIMEUP, MATE, ABANDONED }
+
+use Ending::*;
+
+impl fmt::Display for Ending {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      let word = match self {
+         RESIGNS => "Resigns", SENNICHITE => "Sennichite", JISHOGI => "Jishogi",
+         TIMEUP => "Timeout", MATE => "Mate", ABANDONED => "Abandoned"
+      };
+      write!(f, "{word}")
+   }
+}
+
+#[derive(Debug,Clone,PartialEq)]
+pub struct Outcome {
+   pub ending: Ending,
+   pub black_time: Duration,
+   pub white_time: Duration,
+   pub comment: Comment
+}
+
+fn ending_of(cst: &Cst, line: &Line) -> ErrW7A<Ending> {
+   let word = line.token(ENDING).map_or(line.span, |t| t.span);
+   parse_ending(cst.text(word)).ok_or_else(|| {
+      W7AErr::new(BADMOVE, "Not a game result").at(cst, word)
+   })
+}
+
+fn parse_ending(word: &str) -> Option<Ending> {
+   match word {
+      "Resigns" | "Resigned" => Some(RESIGNS),
+      "Sennichite" | "Repetition" => Some(SENNICHITE),
+      "Jishogi" | "Impasse" => Some(JISHOGI),
+      "Time" | "Timeout" => Some(TIMEUP),
+      "Mate" | "Checkmate" => Some(MATE),
+      "Abandoned" | "Interrupted" => Some(ABANDONED),
+      _ => None
+   }
 }
 
 // ----- PIECES -------------------------------------------------------
 
 #[derive(Debug,Clone,PartialEq)]
 pub struct Piece {
-   promoted: bool,
-   piece: PieceType
+   pub promoted: bool,
+   pub piece: PieceType
 }
 
-#[derive(Debug,Clone,PartialEq)]
-pub enum PieceType { PAWN }
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Hash,PartialOrd,Ord)]
+pub enum PieceType { PAWN, LANCE, KNIGHT, SILVER, GOLD, BISHOP, ROOK, KING }
 
 use PieceType::*;
 
+impl PieceType {
+   /// Golds and kings are the pieces that never promote
+   pub fn promotes(&self) -> bool { !matches!(self, GOLD | KING) }
+}
+
 // ----- POSITION -------------------------------------------------------
 
+/// A square: x is the file, 1 - 9, y is the rank, "a" - "i", with "a"
+/// being White's back rank.
+
 #[derive(Debug,Clone,PartialEq)]
-struct Position { x: usize, y: String } 
+pub struct Position { pub x: usize, pub y: String } 
+
+impl Position {
+   /// the rank as a number, "a" is 1 ... "i" is 9
+   pub fn rank(&self) -> usize {
+      self.y.chars()
+          .next()
+          .map(|c| (c as usize).saturating_sub('a' as usize) + 1)
+          .unwrap_or(0)
+   }
+}
+
+impl fmt::Display for Position {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      write!(f, "{}{}", self.x, self.y)
+   }
+}
 
 // ----- helper functions for scanning the W7A file -------------------------
 
@@ -133,13 +403,28 @@ fn parse_piece(move_line: &Vec<char>) -> ErrStr<(Piece, Vec<char>)> {
    Ok((Piece { piece, promoted }, t))
 }
 
-fn parse_piece_type(c: char) -> ErrStr<PieceType> {
+pub(crate) fn parse_piece_type(c: char) -> ErrStr<PieceType> {
    match c.to_ascii_uppercase() {
       'P' => Ok(PAWN),
+      'L' => Ok(LANCE),
+      'N' => Ok(KNIGHT),
+      'S' => Ok(SILVER),
+      'G' => Ok(GOLD),
+      'B' => Ok(BISHOP),
+      'R' => Ok(ROOK),
+      'K' => Ok(KING),
       _   => Err(format!("No piece exists for char {c}"))
    }
 }
 
+/// The letter of the piece, as w7a and SFEN write it; Black's, in SFEN
+pub(crate) fn piece_letter(piece: PieceType) -> char {
+   match piece {
+      PAWN => 'P', LANCE => 'L', KNIGHT => 'N', SILVER => 'S',
+      GOLD => 'G', BISHOP => 'B', ROOK => 'R', KING => 'K'
+   }
+}
+
 fn parse_promotion(chars: &Vec<char>) -> ErrStr<(bool, Vec<char>)> {
    let (h, t) = ht(&chars);
    let promoted = h == Some('+');
@@ -147,68 +432,237 @@ fn parse_promotion(chars: &Vec<char>) -> ErrStr<(bool, Vec<char>)> {
    Ok((promoted, rest))
 }
 
-fn is_move(line: &str) -> bool {
-   if line.is_empty() {
+// A move line is numbered, "75.G3bx3c", and followed by nothing but clocks.
+// The clocks are what tell "77.B5e! which is a mating threat" (a comment
+// that happens to start a line) from a move.
+pub(crate) fn is_move(line: &str) -> bool {
+   let mut cols = line.split_whitespace();
+   let numbered = cols.next()
+                      .and_then(|col| col.split_once("."))
+                      .is_some_and(|(num, _)| {
+                         !num.is_empty() && num.chars().all(|c| c.is_ascii_digit())
+                      });
+   numbered && cols.all(is_clock)
+}
+
+// a header tag, [Key "value"]; "[Diagram 1]", in a comment, is none
+pub(crate) fn is_tag(line: &str) -> bool {
+   line.trim().strip_prefix('[')
+       .and_then(|tag| tag.strip_suffix(']'))
+       .and_then(|tag| tag.split_once(char::is_whitespace))
+       .is_some_and(|(key, value)| {
+          let value = value.trim();
+          !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric())
+             && value.len() >= 2 && value.starts_with('"') && value.ends_with('"')
+       })
+}
+
+// The game ends where the next begins: at a header tag, after its first move,
+// that follows the result or a blank line (as in an archive). Given the
+// game's lines in turn, GameEnd says which begins the next game.
+#[derive(Debug,Clone,Default)]
+pub(crate) struct GameEnd {
+   started: bool,   // a move, or the result, has been seen
+   boundary: bool   // the line before was blank, or the result
+}
+
+impl GameEnd {
+   pub(crate) fn begins_next(&mut self, line: &str) -> bool {
+      if self.started && self.boundary && is_tag(line) { return true; }
+      self.started = self.started || is_move(line) || is_terminal(line);
+      self.boundary = line.trim().is_empty() || is_terminal(line);
       false
-   } else {
-      let thunks: Vec<&str> = line.split(".").collect();
-      thunks.first()
-            .and_then(|word| Some(word.chars().all(|c| c.is_ascii_digit())))
-            .or(Some(false))
-            .unwrap()
    }
 }
 
-/*
-fn parse_move(line: &String) -> ErrStr<Move> {
-   if !is_move(line) {
-      Err(format!("line {line} is not a move"))
-   } else {
-      parse_move1(line)
-   }
+fn game_end<S: AsRef<str>>(lines: &[S]) -> usize {
+   let mut end = GameEnd::default();
+   lines.iter().position(|line| end.begins_next(line.as_ref())).unwrap_or(lines.len())
 }
 
-fn parse_move1(line: &String) -> ErrStr<Move> {
-   
+// a move sealed at the end of a day's play is marked so, after its line
+pub(crate) fn is_sealed(line: &str) -> bool {
+   line.trim().eq_ignore_ascii_case("Sealed move")
 }
-*/
 
-fn collect_comment(lines: &[String]) -> ErrStr<(Comment, Vec<String>)> {
-   let mut comment_lines: Vec<String> = Vec::new();
-   let mut file = lines.to_vec();
-   loop {
-      if let (Some(line), rest) = ht(&file) {
-         if is_move(&line) { break; }
-         comment_lines.push(line);
-         file = rest.clone();
-         if rest.is_empty() { break; }
-      } else {
-         break;
-      }
+pub(crate) fn is_terminal(line: &str) -> bool {
+   let mut cols = line.split_whitespace();
+   cols.next().is_some_and(|word| parse_ending(word).is_some())
+      && cols.all(is_clock)
+}
+
+pub(crate) fn is_clock(col: &str) -> bool {
+   let parts: Vec<&str> = col.split(":").collect();
+   parts.len() == 3
+      && parts.iter()
+              .all(|p| !p.is_empty() && p.chars().all(|c| c.is_ascii_digit()))
+}
+
+// each move is numbered one more than the move before it; the first, as the
+// setup says
+fn numbered(cst: &Cst, line: &Line, n: usize, expected: usize, first: bool)
+      -> ErrW7A<()> {
+   let msg = match n {
+      _ if n == expected => return Ok(()),
+      _ if first => format!("The first move is {expected}, not {n}"),
+      _ if n < expected => format!("Move {n} again, after move {}", expected - 1),
+      _ => format!("Move {n} follows move {}: skipped {expected}", expected - 1)
    };
-   Ok((if comment_lines.iter().all(String::is_empty) {
-      None
+   let number = line.token(NUMBER).map_or(line.span, |t| t.span);
+   Err(W7AErr::new(SEQUENCE, &msg).at(cst, number).on_move(n))
+}
+
+// the errors point at the move-number, the notation, or the clock at fault
+// the move, but for its clocks
+fn motion_of(cst: &Cst, line: &Line, color: Color) -> ErrW7A<Move> {
+   let span_of = |kind| line.token(kind).map_or(line.span, |t| t.span);
+   let number = span_of(NUMBER);
+   let n: usize = cst.text(number).parse().map_err(|_| {
+      W7AErr::new(BADMOVE, "Cannot read the move number").at(cst, number)
+   })?;
+   let notation = span_of(NOTATION);
+   let bad = |msg: String| W7AErr::new(BADMOVE, &msg).at(cst, notation).on_move(n);
+   // the mark of a check, or mate, is the record's claim, for validate
+   let (text, _) = marked(cst.text(notation));
+   let chars: Vec<char> = text.chars().collect();
+   let (piece, rest) = parse_piece(&chars).map_err(bad)?;
+   let (from, to, capture, promote) = parse_motion(&rest).map_err(bad)?;
+   Ok(Move { n, color, piece, drop: from.is_none(), from, to, capture, promote,
+             total_time: Duration::zero(), other_time: Duration::zero(),
+             comment: None })
+}
+
+type Motion = (Option<Position>, Position, bool, Option<bool>);
+
+// after the piece comes either a drop, "*6d", or a board-move, "2bx7g+"
+fn parse_motion(chars: &[char]) -> ErrStr<Motion> {
+   let bad = || {
+      let motion: String = chars.iter().collect();
+      format!("Cannot scan {motion} as a drop (*6d) or a board-move (2bx7g+)")
+   };
+   if chars.first() == Some(&'*') {
+      let to = parse_position(chars.get(1..3).ok_or_else(bad)?)?;
+      if chars.len() > 3 { return Err(bad()); }
+      Ok((None, to, false, None))
    } else {
-      Some(comment_lines.join(" "))
-   }, file))
-}
-
-fn ingest_header(lines: &[String]) -> ErrStr<(Header, Vec<String>)> {
-   let (hdr, tail): (Vec<&String>, Vec<&String>) =
-      lines.into_iter().partition(|line| line.starts_with("["));
-   let hash: Lookup = hdr.into_iter()
-                         .filter_map(compose!(Result::ok)(scan_header_line))
-                         .collect();
-   let rest: Vec<String> = tail.into_iter().map(String::to_string).collect();
-   Ok((Header { header: hash },rest))
-}
-
-fn scan_header_line(line: &String) -> ErrStr<(String, String)> {
-   let tokens: Vec<String> = line.split("\"").map(to_string).collect();
-   let key = tokens.first().and_then(|k| {
-      let k1: String = k.chars().filter(|c| c.is_alphabetic()).collect();
-      Some(k1)
-   }).ok_or("Cannot get key from empty line".to_string())?;
+      let from = parse_position(chars.get(0..2).ok_or_else(bad)?)?;
+      let capture = match chars.get(2) {
+         Some('-') => false,
+         Some('x') => true,
+         _ => return Err(bad())
+      };
+      let to = parse_position(chars.get(3..5).ok_or_else(bad)?)?;
+      let promote = match chars.get(5..) {
+         Some([]) => None,
+         Some(['+']) => Some(true),
+         Some(['=']) => Some(false),
+         _ => return Err(bad())
+      };
+      Ok((Some(from), to, capture, promote))
+   }
+}
+
+fn parse_position(chars: &[char]) -> ErrStr<Position> {
+   match chars {
+      [file, rank] if ('1'..='9').contains(file) && ('a'..='i').contains(rank) => {
+         let x = file.to_digit(10).unwrap_or(0) as usize;
+         Ok(Position { x, y: rank.to_string() })
+      },
+      _ => {
+         let pos: String = chars.iter().collect();
+         Err(format!("No square {pos} on the board"))
+      }
+   }
+}
+
+// the (cumulative) clocks, Black's then White's; both optional
+fn clocks_of(cst: &Cst, line: &Line, n: Option<usize>)
+      -> ErrW7A<(Duration, Duration)> {
+   let mut clocks = [Duration::zero(), Duration::zero()];
+   for (clock, span) in clocks.iter_mut().zip(line.clocks()) {
+      *clock = parse_clock(cst.text(span)).map_err(|msg| {
+         let err = W7AErr::new(BADCLOCK, &msg).at(cst, span);
+         match n { Some(n) => err.on_move(n), None => err }
+      })?;
+   }
+   Ok((clocks[0], clocks[1]))
+}
+
+fn parse_clock(clock: &str) -> ErrStr<Duration> {
+   let parts: Vec<i64> =
+      clock.split(":")
+           .map(|p| err_or(p.parse(), &format!("Bad clock value {clock}")))
+           .collect::<ErrStr<_>>()?;
+   match parts[..] {
+      [_, m, s] if m >= 60 || s >= 60 =>
+         Err(format!("Clock {clock} has more than 59 minutes or seconds")),
+      [h, m, s] =>
+         Duration::try_hours(h)
+            .map(|hours| hours + Duration::minutes(m) + Duration::seconds(s))
+            .ok_or(format!("Clock {clock} is out of range")),
+      _ => Err(format!("Clock {clock} is not of the form hh:mm:ss"))
+   }
+}
+
+// [Black "Habu Yoshiharu, Oi"]
+fn tag_of(cst: &Cst, line: &Line) -> ErrW7A<(String, String)> {
+   match (line.token(NAME), line.token(VALUE)) {
+      (Some(name), Some(value)) =>
+         Ok((cst.text(name.span).to_string(), cst.text(value.span).to_string())),
+      _ => Err(W7AErr::new(HEADER, "Expected a tag of the form [Name \"value\"]")
+                      .at(cst, line.span))
+   }
+}
+
+/// The moves of a [Sealed "42"] tag, or of [Sealed "42, 87"]
+pub fn sealed_of(value: &str) -> ErrStr<Vec<usize>> {
+   value.split(',')
+        .map(|n| err_or(n.trim().parse(),
+                        &format!("Sealed move {n} is not a move number")))
+        .collect()
+}
+
+// A comment is one or more paragraphs, separated by blank lines. The lines of
+// a paragraph are joined into one; the paragraphs are kept apart by "\n\n".
+fn collect_comment<S: AsRef<str>>(lines: &[S]) -> ErrStr<(Comment, &[S])> {
+   let end = lines.iter()
+                  .position(|line| is_move(line.as_ref()) || is_terminal(line.as_ref()))
+                  .unwrap_or(lines.len());
+   Ok((paragraphs(&lines[..end]), &lines[end..]))
+}
+
+pub(crate) fn paragraphs<S: AsRef<str>>(lines: &[S]) -> Comment {
+   let paras: Vec<String> =
+      lines.split(|line| line.as_ref().trim().is_empty())
+           .filter(|para| !para.is_empty())
+           .map(|para| {
+              let words: Vec<&str> =
+                 para.iter().flat_map(|line| line.as_ref().split_whitespace()).collect();
+              words.join(" ")
+           })
+           .collect();
+   if paras.is_empty() { None } else { Some(paras.join("\n\n")) }
+}
+
+// The header is the tags at the top of the file (blank lines between them
+// are no matter); the rest starts after the last of them.
+fn ingest_header<S: AsRef<str>>(lines: &[S]) -> ErrStr<(Header, &[S])> {
+   let tag = |line: &S| is_tag(line.as_ref());
+   let top = lines.iter()
+                  .position(|line| !tag(line) && !line.as_ref().trim().is_empty())
+                  .unwrap_or(lines.len());
+   let end = lines[..top].iter().rposition(tag).map_or(0, |i| i + 1);
+   let hash: Lookup = lines[..end].iter()
+                                  .filter_map(|line| scan_header_line(line.as_ref()).ok())
+                                  .collect();
+   Ok((Header { header: hash }, &lines[end..]))
+}
+
+fn scan_header_line(line: &str) -> ErrStr<(String, String)> {
+   let tokens: Vec<&str> = line.split("\"").collect();
+   let key: String = tokens.first()
+                           .map(|k| k.chars().filter(|c| c.is_alphabetic()).collect())
+                           .ok_or("Cannot get key from empty line".to_string())?;
    let value = tokens.get(1).ok_or(format!("No quotes in header line {line}"))?;
    Ok((key, value.to_string()))
 }
@@ -218,16 +672,65 @@ fn scan_header_line(line: &String) -> ErrStr<(String, String)> {
 enum ParserState { START, HEADER, MOVES, END };
 */
 
+// ----- ENTRY POINTS -------------------------------------------------------
+
+// Nothing here panics: a missing or unreadable file, or a malformed record,
+// is an Err, so that a long-running service embedding the parser carries on.
+
 fn load_file(filename: &str) -> ErrStr<Vec<String>> {
    // test filename = "data/tests/sample-header.w7a"
-   let lines = lines_from_file(filename);
-   assert!(lines.is_ok());
-   lines
+   Ok(read_file(filename)?.lines().map(String::from).collect())
+}
+
+fn read_file(filename: &str) -> ErrW7A<String> {
+   fs::read_to_string(filename).map_err(|e| {
+      W7AErr::new(READ, &format!("Cannot read {filename}: {e}")).in_file(filename)
+   })
 }
 
 pub fn load_w7a_header(filename: &str) -> ErrStr<(Header, Vec<String>)> {
    let lines = load_file(filename)?;
-   ingest_header(&lines)
+   let (header, rest) = Header::ingest(&lines)?;
+   Ok((header, rest.to_vec()))
+}
+
+/// The syntax tree of the file, named for its diagnostics
+pub fn load_cst(filename: &str) -> ErrW7A<Cst<'static>> {
+   Ok(Cst::parse(read_file(filename)?).named(filename))
+}
+
+pub fn load_w7a(filename: &str) -> ErrW7A<W7A> {
+   W7A::from_cst(&load_cst(filename)?)
+}
+
+/// As much of the game as could be read, and every error and warning found;
+/// only an unreadable file is an Err
+pub fn recover_w7a(filename: &str) -> ErrW7A<(W7A, Vec<W7AErr>)> {
+   Ok(W7A::recover(&load_cst(filename)?))
+}
+
+/// The game of a w7a record held in memory, e.g. a request body
+pub fn parse_w7a(source: &str) -> ErrW7A<W7A> {
+   W7A::from_cst(&Cst::parse(source))
+}
+
+/// The game of a w7a record read from a buffered reader, e.g. a request body
+pub fn parse_reader(reader: impl BufRead) -> ErrW7A<W7A> {
+   read_w7a(reader)
+}
+
+/// The game of a w7a record read to its end, from a socket, stdin, ...
+pub fn read_w7a(mut reader: impl Read) -> ErrW7A<W7A> {
+   let mut source = String::new();
+   reader.read_to_string(&mut source).map_err(|e| {
+      W7AErr::new(READ, &format!("Cannot read the w7a record: {e}"))
+   })?;
+   parse_w7a(&source)
+}
+
+pub fn scan_w7a<S: AsRef<str>>(lines: &[S]) -> ErrW7A<W7A> {
+   let source: Vec<&str> = lines.iter().map(AsRef::as_ref).collect();
+   W7A::from_cst(&Cst::parse(source.join("\n")))
 }
 
 // ----- TESTS -------------------------------------------------------
@@ -236,6 +739,17 @@ pub fn load_w7a_header(filename: &str) -> ErrStr<(Header, Vec<String>)> {
 mod tests {
    use super::*;
 
+   const HIRATE: &str = "lnsgkgsnl/1r5b1/ppppppppp/9/9/9/PPPPPPPPP/1B5R1/LNSGKGSNL";
+
+   // a move line on its own, as though the game were taken up at that move
+   fn parse_move(line: &str) -> ErrW7A<Move> {
+      let n: usize = line.split('.').next().and_then(|n| n.parse().ok()).unwrap_or(1);
+      let side = if n % 2 == 1 { "b" } else { "w" };
+      let source = format!("[SFEN \"{HIRATE} {side} - {n}\"]\n{line}");
+      let game = W7A::from_cst(&Cst::parse(source))?;
+      game.moves.first().cloned().ok_or(W7AErr::new(BADMOVE, "Not a move line"))
+   }
+
    // --- HEADER TESTS ----------------------------------------
    #[test]
    fn test_scan_header_line() {
@@ -313,6 +827,19 @@ mod tests {
       Ok(())
    }
 
+   // borrowed lines, and a cursor into them: no line is copied but to join it
+   #[test]
+   fn test_long_commentary() -> ErrStr<()> {
+      let line = "One of very many lines of commentary.";
+      let mut lines: Vec<&str> = vec![line; 100_000];
+      lines.push("1.P7g-7f     00:00:00  00:00:00");
+      let (game_comment, rest) = GameComment::ingest(&lines)?;
+      assert_eq!(["1.P7g-7f     00:00:00  00:00:00"], rest);
+      let comment = game_comment.comment.ok_or("No comment scanned")?;
+      assert_eq!(100_000 * (line.len() + 1) - 1, comment.len());
+      Ok(())
+   }
+
    #[test]
    fn test_ingest_game_comment_no_moves() -> ErrStr<()> {
       let file = load_game_comment()?;
@@ -340,14 +867,158 @@ mod tests {
       assert!(!is_move(&format!("{sentence1}. {sentence2}")));
    }
 
+   #[test]
+   fn fail_move_in_comment() {
+      let line = "77.B5e! which is a mating threat that defends against the";
+      assert!(!is_move(line));
+   }
+
+   #[test]
+   fn test_terminal_line() {
+      assert!(is_terminal("Resigns      07:55:00  07:59:00"));
+      assert!(!is_terminal("Resigning was the only option here."));
+   }
+
+   #[test]
+   fn test_parse_move() -> ErrStr<()> {
+      let mov = parse_move("10.B2bx7g+   00:10:00  00:11:00")?;
+      assert_eq!(10, mov.n);
+      assert_eq!(BISHOP, mov.piece.piece);
+      assert_eq!(Some(Position { x: 2, y: "b".to_string() }), mov.from);
+      assert_eq!(Position { x: 7, y: "g".to_string() }, mov.to);
+      assert!(mov.capture);
+      assert_eq!(Some(true), mov.promote);
+      assert_eq!(Duration::minutes(11), mov.total_time);
+      Ok(())
+   }
+
+   #[test]
+   fn test_parse_drop() -> ErrStr<()> {
+      let mov = parse_move("47.B*6d      04:34:00  04:30:00")?;
+      assert!(mov.drop);
+      assert_eq!(None, mov.from);
+      assert_eq!(4, mov.to.rank());
+      assert_eq!(Duration::minutes(274), mov.total_time);
+      Ok(())
+   }
+
+   #[test]
+   fn fail_parse_move() {
+      assert!(parse_move("12.S3a-4z    00:10:00  00:11:00").is_err());
+      assert!(parse_move("12.S3a~4b    00:10:00  00:11:00").is_err());
+   }
+
+   const PROOF: &str = "[Black \"Habu\"]\n\
+                        [Black \"Hane\"]\n\
+                        1.P7g-7f     00:00:00  00:00:00\n\
+                        2.Q3c-3d     00:00:00  00:01:00\n\
+                        3.P2g-2f     00:03:00  00:01:00\n\
+                        4.P8c-8d     00:03:00  00:77:00\n\
+                        Resigns      00:05:00  00:02:00\n\
+                        5.P2f-2e     00:06:00  00:02:00\n";
+
+   #[test]
+   fn test_recover_all_errors() {
+      use crate::types::error::ErrorKind;
+      let (game, found) = W7A::recover(&Cst::parse(PROOF));
+      let kinds: Vec<(ErrorKind, Option<usize>)> =
+         found.iter().map(|e| (e.kind, e.line())).collect();
+      assert_eq!(vec![(HEADER, Some(2)), (BADMOVE, Some(4)), (BADCLOCK, Some(6)),
+                      (SEQUENCE, Some(8))], kinds);
+      assert_eq!(2, found.iter().filter(|e| e.is_error()).count());
+      let ns: Vec<usize> = game.moves.iter().map(|m| m.n).collect();
+      assert_eq!(vec![1, 3, 4], ns);
+      // the bad clock is taken to be what the clocks were after move 3
+      assert_eq!(Duration::minutes(1), game.moves[2].total_time);
+      assert!(game.outcome.is_some());
+   }
+
+   #[test]
+   fn fail_strict_at_first_error() {
+      let err = W7A::from_cst(&Cst::parse(PROOF)).unwrap_err();
+      assert_eq!((BADMOVE, Some(4)), (err.kind, err.line()));
+   }
+
+   #[test]
+   fn test_recover_clean_game() -> ErrStr<()> {
+      let dir = "../data/game_records/reijer_grimberger";
+      let (game, found) = recover_w7a(&format!("{dir}/2013-07-11-54th-oi-sen-game-1.w7a"))?;
+      assert!(found.is_empty());
+      assert_eq!(83, game.moves.len());
+      Ok(())
+   }
+
+   #[test]
+   fn fail_missing_file() {
+      assert!(load_w7a_header("data/tests/no-such-file.w7a").is_err());
+      let err = load_w7a("data/tests/no-such-file.w7a").unwrap_err();
+      assert_eq!(READ, err.kind);
+   }
+
+   #[test]
+   fn test_parse_and_read_in_memory() -> ErrStr<()> {
+      let record = "[Black \"Habu\"]\n1.P7g-7f     00:00:00  00:00:00\n";
+      let parsed = parse_w7a(record)?;
+      assert_eq!(1, parsed.moves.len());
+      assert_eq!(parsed, read_w7a(record.as_bytes())?);
+      Ok(())
+   }
+
+   #[test]
+   fn test_from_str_and_reader() -> ErrStr<()> {
+      let record = "[Black \"Habu\"]\n\nA comment\n\
+                    1.P7g-7f     00:00:00  00:00:00\n\
+                    Resigns      00:00:00  00:00:00\n";
+      let game: W7A = record.parse()?;
+      assert_eq!(Some("Habu"), game.tag("Black"));
+      assert_eq!(Some("A comment"), game.comment());
+      assert_eq!(PAWN, game.moves()[0].piece.piece);
+      assert_eq!(Some(RESIGNS), game.outcome().map(|o| o.ending.clone()));
+      assert_eq!(game, parse_reader(std::io::Cursor::new(record))?);
+      Ok(())
+   }
+
+   #[test]
+   fn fail_clock_out_of_range() {
+      assert!(parse_clock("9999999999999999:00:00").is_err());
+      assert!(parse_move("1.P7g-7f  99999999999999999999:00:00").is_err());
+   }
+
+   #[test]
+   fn test_error_locations() {
+      let lines: Vec<String> = ["[Black \"Habu\"]", "1.P7g-7f  00:00:00",
+                                "2.Q3c-3d  00:00:00  00:00:07"]
+                                  .into_iter().map(String::from).collect();
+      let err = scan_w7a(&lines).unwrap_err();
+      assert_eq!((BADMOVE, Some(3), Some(3), Some(2)),
+                 (err.kind, err.line(), err.column(), err.n));
+      let clock = parse_move("12.S3a-4b    00:10:00  00:61:00").unwrap_err();
+      assert_eq!((BADCLOCK, Some(24)), (clock.kind, clock.column()));
+   }
+
+   #[test]
+   fn test_bracketed_game_comment() -> ErrStr<()> {
+      // only [Key "value"] is a tag; any other bracket is comment text
+      let game: W7A = "[Black \"Habu\"]\n\
+                       [Diagram 1] the position after the furigoma\n\
+                       [Black Habu]\n\
+                       1.P7g-7f     00:00:00  00:00:00\n".parse()?;
+      assert_eq!(1, game.header.header.len());
+      assert_eq!(Some("[Diagram 1] the position after the furigoma [Black Habu]"),
+                 game.comment());
+      Ok(())
+   }
+
 // Move-format: 1.P7g-7f     00:00:00  00:00:00
 
    fn mk_test_move(n: usize, seggs: i64) -> Move {
       let total_time = Duration::seconds(seggs);
       let piece = Piece { promoted: false, piece: PAWN };
       let to = Position { x: 7, y: "d".to_string() };
-      Move { n, piece, from: None, to, capture: false, 
-             drop: false, total_time, comment: None }
+      let color = if n % 2 == 1 { BLACK } else { WHITE };
+      Move { n, color, piece, from: None, to, capture: false, 
+             drop: false, promote: None, total_time,
+             other_time: Duration::zero(), comment: None }
    }
 
    // --- Duration ----------------------------------------
@@ -367,16 +1038,46 @@ mod tests {
 
    // --- Color --------------------------------------------------
 
+   fn colors(game: &W7A) -> Vec<Color> { game.moves.iter().map(color).collect() }
+
    #[test]
-   fn test_sente_color() {
-      let sente = mk_test_move(17, 99);
-      assert_eq!(BLACK, color(&sente));
+   fn test_sente_first() -> ErrStr<()> {
+      let game: W7A = "1.P7g-7f     00:00:00  00:00:00\n\
+                       2.P3c-3d     00:00:00  00:00:00\n".parse()?;
+      assert_eq!(vec![BLACK, WHITE], colors(&game));
+      Ok(())
+   }
+
+   #[test]
+   fn test_gote_first() -> ErrStr<()> {
+      let handicap: W7A = "[Handicap \"Bishop\"]\n\
+                           1.P3c-3d     00:00:00  00:00:00\n\
+                           2.P7g-7f     00:00:00  00:00:00\n".parse()?;
+      assert_eq!(vec![WHITE, BLACK], colors(&handicap));
+      let midway: W7A = "[SFEN \"8k/9/9/9/9/9/9/9/K8 b G 41\"]\n\
+                         41.G*2b      00:00:00  00:00:00\n".parse()?;
+      assert_eq!(vec![BLACK], colors(&midway));
+      Ok(())
+   }
+
+   #[test]
+   fn fail_move_after_result() {
+      let late = "1.P7g-7f     00:00:00  00:00:00\n\
+                  Resigns      00:00:00  00:00:00\n\
+                  2.P3c-3d     00:00:00  00:00:00\n".parse::<W7A>().unwrap_err();
+      assert_eq!((SEQUENCE, Some(3)), (late.kind, late.line()));
+      assert_eq!("The game is over: this line comes after the result", late.message);
    }
 
    #[test]
-   fn test_gote_color() {
-      let gote = mk_test_move(18, 999);
-      assert_eq!(WHITE, color(&gote));
+   fn fail_numbering() {
+      let skipped = "1.P7g-7f     00:00:00  00:00:00\n\
+                     3.P3c-3d     00:00:00  00:00:00\n".parse::<W7A>().unwrap_err();
+      assert_eq!((SEQUENCE, Some(2), Some(1)), (skipped.kind, skipped.line(), skipped.column()));
+      assert_eq!("Move 3 follows move 1: skipped 2", skipped.message);
+      let first = "[SFEN \"8k/9/9/9/9/9/9/9/K8 b G 41\"]\n\
+                   1.G*2b       00:00:00  00:00:00\n".parse::<W7A>().unwrap_err();
+      assert_eq!("The first move is 41, not 1", first.message);
    }
 
    // --- pieces -------------------------------------------------
@@ -466,9 +1167,74 @@ mod tests {
    #[test]
    fn test_create_w7a_from_scan() -> ErrStr<()> {
       let file = load_oi_game()?;
-      let (_game, rest) = W7A::ingest(&file)?;
+      let (game, rest) = W7A::ingest(&file)?;
+      assert!(rest.is_empty());
+      assert_eq!(83, game.moves.len());
+      let two: Vec<String> = file.iter().chain(file.iter()).cloned().collect();
+      let (first, rest) = W7A::ingest(&two)?;
+      assert_eq!(game, first);
+      assert_eq!(file.len(), rest.len());
+      assert!(rest[0].starts_with("[Black"));
+      let (second, rest) = W7A::ingest(rest)?;
+      assert_eq!(game, second);
       assert!(rest.is_empty());
       Ok(())
    }
-}
 
+   #[test]
+   fn test_ingest_moves_and_outcome() -> ErrStr<()> {
+      let file = load_oi_game()?;
+      let (game, _rest) = W7A::ingest(&file)?;
+      assert_eq!(83, game.moves.len());
+      assert!(game.moves[0].comment.is_some());
+      assert!(game.moves[75].comment.is_some()); // the 77.B5e! comment
+      let outcome = game.outcome.ok_or("No outcome scanned")?;
+      assert_eq!(RESIGNS, outcome.ending);
+      assert_eq!(Duration::minutes(479), outcome.white_time);
+      assert!(outcome.comment.is_some());
+      Ok(())
+   }
+
+   #[test]
+   fn test_comment_paragraphs() -> ErrStr<()> {
+      let file = load_oi_game()?;
+      let (game, _rest) = W7A::ingest(&file)?;
+      let comment = game.moves[41].comment.clone().ok_or("No comment on 42")?;
+      let paras: Vec<&str> = comment.split("\n\n").collect();
+      assert_eq!(2, paras.len());
+      assert_eq!("Diagram 1", paras[0]);
+      assert!(paras[1].starts_with("The Kakugawari opening, where"));
+      assert!(paras[1].contains("how to push their pawns"));
+      Ok(())
+   }
+
+   #[test]
+   fn test_sealed_moves() -> ErrStr<()> {
+      let game: W7A = "1.P7g-7f     00:00:00  00:00:00\n\
+                       2.P3c-3d     00:00:00  00:00:00\n\
+                       Sealed move\n\
+                       \n\
+                       Diagram 1\n\
+                       3.P2g-2f     00:00:00  00:00:00\n".parse()?;
+      assert_eq!(vec![2], game.sealed);
+      assert_eq!(Some("Diagram 1"), game.moves[1].comment.as_deref());
+      let tagged: W7A = "[Sealed \"3, 1\"]\n\
+                         1.P7g-7f     00:00:00  00:00:00\n\
+                         Sealed move\n\
+                         2.P3c-3d     00:00:00  00:00:00\n\
+                         3.P2g-2f     00:00:00  00:00:00\n".parse()?;
+      assert_eq!(vec![1, 3], tagged.sealed);
+      Ok(())
+   }
+
+   #[test]
+   fn fail_bad_sealed_tag() -> ErrStr<()> {
+      // before the moves, it is only the game comment
+      let game: W7A = "Sealed move\n1.P7g-7f     00:00:00  00:00:00\n".parse()?;
+      assert!(game.sealed.is_empty());
+      let err = "[Sealed \"one\"]\n1.P7g-7f     00:00:00  00:00:00\n".parse::<W7A>()
+                   .expect_err("a bad Sealed tag");
+      assert_eq!(HEADER, err.kind);
+      Ok(())
+   }
+}
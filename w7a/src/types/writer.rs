@@ -0,0 +1,596 @@
+use std::{
+   fmt,
+   path::Path
+};
+
+use chrono::{Datelike, Duration, NaiveDate};
+
+use shogi_kifu_converter::{
+   jkf::{
+      Color as JColor, JsonKifuFormat, Kind, MoveMoveFormat, MoveSpecial,
+      PlaceFormat, Preset, StateFormat, TimeFormat
+   },
+   parser::{parse_csa_file, parse_jkf_file, parse_ki2_file, parse_kif_file}
+};
+
+use book::err_utils::ErrStr;
+
+use super::{
+   board::{Board, Occupant, position},
+   check::checks,
+   clock::TimeControl,
+   setup::{handicap_name, setup, to_sfen},
+   utils::Lookup,
+   w7a::{
+      W7A, Header, GameComment, Move, Outcome, Ending, Ending::*, Comment,
+      Piece, PieceType::*, Color, Color::*, color, paragraphs, piece_letter,
+      scan_w7a, sealed_of
+   }
+};
+
+// The way back: from JKF (and so from anything shogi-kifu-converter reads:
+// KIF, KI2 and CSA) to w7a, e.g.:
+
+// [Black "Habu Yoshiharu, Oi"]
+// [White "Namekata Hisashi, Challenger"]
+// [Event "54th Oi-sen, Game 1"]
+// [Date "July 10th and 11th 2013"]
+// 1.P7g-7f     00:00:00  00:00:00
+// ...
+// Resigns      07:55:00  07:59:00
+
+// The W7A is written (Displayed) canonically: header tags in a fixed order,
+// moves and clocks in aligned columns, comment paragraphs wrapped at WIDTH.
+
+const WIDTH: usize = 85;
+
+/// Reads a KIF (.kif is Shift_JIS, .kifu is UTF-8), KI2, CSA or JKF file
+pub fn read_kifu(filename: &str) -> ErrStr<JsonKifuFormat> {
+   let path = Path::new(filename);
+   let ext = path.extension()
+                 .and_then(|ext| ext.to_str())
+                 .map(|ext| ext.to_ascii_lowercase())
+                 .unwrap_or_default();
+   let parsed = match ext.as_str() {
+      "kif" | "kifu" => parse_kif_file(path),
+      "ki2" | "ki2u" => parse_ki2_file(path),
+      "csa" => parse_csa_file(path),
+      "jkf" | "json" => parse_jkf_file(path),
+      _ => return Err(format!("Cannot tell the format of {filename} from its \
+                               extension (kif, kifu, ki2, csa or jkf)"))
+   };
+   parsed.map_err(|e| format!("Cannot parse {filename}: {e}"))
+}
+
+/// The w7a game record of a JKF
+pub fn to_w7a(jkf: &JsonKifuFormat) -> ErrStr<String> {
+   Ok(from_jkf(jkf)?.to_string())
+}
+
+/// The game record, written canonically, but only if it reads back as the
+/// very same game
+pub fn canonical(game: &W7A) -> ErrStr<String> {
+   let text = game.to_string();
+   let lines: Vec<String> = text.lines().map(String::from).collect();
+   if scan_w7a(&lines)? == *game {
+      Ok(text)
+   } else {
+      Err("Reformatting would change the game; is there a comment line that \
+           reads as a move, or as a header tag?".to_string())
+   }
+}
+
+/// The W7A of a JKF, replaying the moves to find the captures
+pub fn from_jkf(jkf: &JsonKifuFormat) -> ErrStr<W7A> {
+   let mut header = Header { header: header_tags(jkf)? };
+   if let Some(initial) = jkf.initial.filter(|i| i.preset != Preset::PresetHirate) {
+      let (tag, value) = match &initial.data {
+         Some(state) => ("SFEN", to_sfen(&board_of(state))),
+         None => ("Handicap", handicap_name(initial.preset).ok_or_else(|| {
+            format!("No handicap, in w7a, for the JKF preset {:?}", initial.preset)
+         })?.to_string())
+      };
+      header.header.insert(tag.to_string(), value);
+   }
+   let start = setup(&header)?;
+   let mut board = start.board;
+   let (opening, rest) = jkf.moves.split_first()
+                            .map(|(o, r)| (Some(o), r))
+                            .unwrap_or((None, &[]));
+   let mut game_comment =
+      GameComment { comment: comment(opening.and_then(|o| o.comments.as_deref())) };
+   let mut clocks = (Duration::zero(), Duration::zero());
+   let mut moves = Vec::new();
+   let mut outcome = None;
+   let mut sealed = Vec::new();
+   let mut n = start.first - 1;  // the entries with a move; a comment may stand alone
+   for jmov in rest {
+      let total = jmov.time.map(|t| duration(&t.total));
+      // the move's side, or, for the result, the side to move
+      let mover = jmov.move_.as_ref().map_or(board.turn.clone(), |m| side_of(m.color));
+      let own = if mover == BLACK { &mut clocks.0 } else { &mut clocks.1 };
+      *own = total.unwrap_or(*own);
+      if let Some(mmov) = &jmov.move_ {
+         n += 1;
+         let mut mov = to_move(n, mmov, &board, clocks)?;
+         board.apply(&mov)?;
+         let (marks, comments) = marks(jmov.comments.as_deref());
+         if marks.contains(&"封じ手") { sealed.push(n); }
+         mov.comment = comment(comments);
+         moves.push(mov);
+      } else if let Some(special) = &jmov.special {
+         outcome = Some(Outcome { ending: ending(special)?,
+                                  black_time: clocks.0, white_time: clocks.1,
+                                  comment: comment(jmov.comments.as_deref()) });
+         break;
+      } else if let Some(aside) = comment(jmov.comments.as_deref()) {
+         // commentary standing alone is of the move before it, or the game
+         let before = match moves.last_mut() {
+            Some(mov) => &mut mov.comment,
+            None => &mut game_comment.comment
+         };
+         *before = Some(match before.take() {
+            Some(said) => format!("{said}\n\n{aside}"),
+            None => aside
+         });
+      }
+   }
+   Ok(W7A { header, game_comment, moves, outcome, sealed })
+}
+
+impl fmt::Display for W7A {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      for line in header_lines(&self.header.header) {
+         writeln!(f, "{line}")?;
+      }
+      write_comment(f, &self.game_comment.comment)?;
+      // the sealed moves the header does not list are marked in the moves
+      let tagged = self.tag("Sealed").and_then(|v| sealed_of(v).ok()).unwrap_or_default();
+      // a game that does not replay is written without its checks
+      let checks = checks(self).unwrap_or_default();
+      for (i, mov) in self.moves.iter().enumerate() {
+         let clocks = if color(mov) == BLACK {
+            (mov.total_time, mov.other_time)
+         } else {
+            (mov.other_time, mov.total_time)
+         };
+         let mark = checks.get(i).and_then(|(_, check)| *check).map_or("", |c| c.suffix());
+         writeln!(f, "{}", move_line(&format!("{}.{}{mark}", mov.n, notation(mov)), clocks))?;
+         if self.sealed.contains(&mov.n) && !tagged.contains(&mov.n) {
+            writeln!(f, "Sealed move")?;
+         }
+         write_comment(f, &mov.comment)?;
+      }
+      if let Some(outcome) = &self.outcome {
+         let clocks = (outcome.black_time, outcome.white_time);
+         writeln!(f, "{}", move_line(&outcome.ending.to_string(), clocks))?;
+         write_comment(f, &outcome.comment)?;
+      }
+      Ok(())
+   }
+}
+
+// ----- HEADER -------------------------------------------------------
+
+// The w7a tags, in the order the w7a header lists them, with their KIF-keys
+const TAGS: [(&str, &str); 4] =
+   [("Black", "先手"), ("White", "後手"), ("Event", "棋戦"), ("Date", "開始日時")];
+
+fn header_tags(jkf: &JsonKifuFormat) -> ErrStr<Lookup> {
+   let mut tags = Lookup::new();
+   for (tag, key) in TAGS {
+      if let Some(value) = jkf.header.get(key) {
+         let value = if tag == "Date" {
+            english_date(value, jkf.header.get("終了日時"))?
+         } else {
+            value.to_string()
+         };
+         tags.insert(tag.to_string(), value);
+      }
+   }
+   // as the converter writes it; any other wording of it we cannot read
+   let control = jkf.header.get("持ち時間").and_then(|v| TimeControl::from_kif(v));
+   if let Some(control) = control {
+      tags.insert("TimeControl".to_string(), control.to_string());
+   }
+   Ok(tags)
+}
+
+// the known tags first, in order, then any others, alphabetically
+fn header_lines(tags: &Lookup) -> Vec<String> {
+   let mut others: Vec<(&String, &String)> =
+      tags.iter()
+          .filter(|(k, _)| !TAGS.iter().any(|(tag, _)| tag == k))
+          .collect();
+   others.sort();
+   TAGS.iter()
+       .filter_map(|(tag, _)| tags.get_key_value(*tag))
+       .chain(others)
+       .map(|(k, v)| header_line(k, v))
+       .collect()
+}
+
+pub fn header_line(tag: &str, value: &str) -> String {
+   format!("[{tag} \"{value}\"]")
+}
+
+// "2013/07/10 09:00:00" is "July 10th 2013" and, should the game finish the
+// next day, "2013/07/10" to "2013/07/11" is "July 10th and 11th 2013"
+fn english_date(start: &str, end: Option<&String>) -> ErrStr<String> {
+   let begin = kif_date(start)?;
+   let finish = end.and_then(|e| kif_date(e).ok())
+                   .filter(|f| *f != begin && f.month() == begin.month()
+                               && f.year() == begin.year());
+   let days = match finish {
+      Some(f) => format!("{} and {}", ordinal(begin.day()), ordinal(f.day())),
+      None => ordinal(begin.day())
+   };
+   Ok(format!("{} {days} {}", month_name(begin.month()), begin.year()))
+}
+
+fn kif_date(date: &str) -> ErrStr<NaiveDate> {
+   let day: String = date.chars().take_while(|c| c.is_ascii_digit() || *c == '/')
+                         .collect();
+   NaiveDate::parse_from_str(&day, "%Y/%m/%d")
+      .map_err(|_| format!("Cannot read the date {date}"))
+}
+
+fn month_name(m: u32) -> &'static str {
+   ["January", "February", "March", "April", "May", "June", "July", "August",
+    "September", "October", "November", "December"][(m as usize - 1) % 12]
+}
+
+fn ordinal(d: u32) -> String {
+   let suffix = match (d % 10, d % 100) {
+      (_, 11..=13) => "th",
+      (1, _) => "st",
+      (2, _) => "nd",
+      (3, _) => "rd",
+      _ => "th"
+   };
+   format!("{d}{suffix}")
+}
+
+// ----- MOVES -------------------------------------------------------
+
+fn to_move(n: usize, mmov: &MoveMoveFormat, board: &Board,
+           (black, white): (Duration, Duration)) -> ErrStr<Move> {
+   let to = square_of(&mmov.to, n)?;
+   let from = mmov.from.as_ref().map(|f| square_of(f, n)).transpose()?;
+   let capture = from.is_some() && board.at(to).is_some();
+   let color = side_of(mmov.color);
+   let (total_time, other_time) =
+      if color == BLACK { (black, white) } else { (white, black) };
+   Ok(Move {
+      n,
+      color,
+      piece: piece(mmov.piece),
+      drop: from.is_none(),
+      from: from.map(position),
+      to: position(to),
+      capture,
+      promote: mmov.promote,
+      total_time,
+      other_time,
+      comment: None
+   })
+}
+
+// the board of a JKF position: board[file - 1][rank - 1], the side to move,
+// and the hands
+fn board_of(state: &StateFormat) -> Board {
+   let mut board = Board::empty();
+   for (x, file) in state.board.iter().enumerate() {
+      for (y, place) in file.iter().enumerate() {
+         if let (Some(color), Some(kind)) = (place.color, place.kind) {
+            board.set((x + 1, y + 1), Some(Occupant { color: side_of(color),
+                                                      piece: piece(kind) }));
+         }
+      }
+   }
+   for (c, hand) in [BLACK, WHITE].iter().zip(state.hands.iter()) {
+      for (p, count) in [(PAWN, hand.FU), (LANCE, hand.KY), (KNIGHT, hand.KE),
+                         (SILVER, hand.GI), (GOLD, hand.KI), (BISHOP, hand.KA),
+                         (ROOK, hand.HI)] {
+         board.give(c, p, count as usize);
+      }
+   }
+   board.turn = side_of(state.color);
+   board
+}
+
+fn side_of(color: JColor) -> Color {
+   match color { JColor::Black => BLACK, JColor::White => WHITE }
+}
+
+fn square_of(place: &PlaceFormat, n: usize) -> ErrStr<(usize, usize)> {
+   let (x, y) = (place.x as usize, place.y as usize);
+   if (1..=9).contains(&x) && (1..=9).contains(&y) {
+      Ok((x, y))
+   } else {
+      Err(format!("Move {n}: no square {x}{y} on the board"))
+   }
+}
+
+/// The w7a piece of a JKF piece-kind
+pub fn piece(kind: Kind) -> Piece {
+   let (piece, promoted) = match kind {
+      Kind::FU => (PAWN, false),
+      Kind::KY => (LANCE, false),
+      Kind::KE => (KNIGHT, false),
+      Kind::GI => (SILVER, false),
+      Kind::KI => (GOLD, false),
+      Kind::KA => (BISHOP, false),
+      Kind::HI => (ROOK, false),
+      Kind::OU => (KING, false),
+      Kind::TO => (PAWN, true),
+      Kind::NY => (LANCE, true),
+      Kind::NK => (KNIGHT, true),
+      Kind::NG => (SILVER, true),
+      Kind::UM => (BISHOP, true),
+      Kind::RY => (ROOK, true)
+   };
+   Piece { promoted, piece }
+}
+
+/// The move as w7a writes it: P7g-7f, B2bx7g+, +P4cx4b, B*6d, S2d-3c=
+pub fn notation(mov: &Move) -> String {
+   let promoted = if mov.piece.promoted { "+" } else { "" };
+   let letter = piece_letter(mov.piece.piece);
+   let motion = match &mov.from {
+      None => format!("*{}", mov.to),
+      Some(from) => {
+         let sep = if mov.capture { "x" } else { "-" };
+         let promote = match mov.promote {
+            Some(true) => "+",
+            Some(false) => "=",
+            None => ""
+         };
+         format!("{from}{sep}{}{promote}", mov.to)
+      }
+   };
+   format!("{promoted}{letter}{motion}")
+}
+
+/// A move (or result) line: the move, then Black's and White's clocks
+pub fn move_line(head: &str, (black, white): (Duration, Duration)) -> String {
+   format!("{head:<12} {}  {}", clock(black), clock(white))
+}
+
+pub(crate) fn clock(d: Duration) -> String {
+   let secs = d.num_seconds();
+   format!("{:02}:{:02}:{:02}", secs / 3600, (secs / 60) % 60, secs % 60)
+}
+
+fn duration(t: &TimeFormat) -> Duration {
+   Duration::hours(t.h.unwrap_or(0) as i64) + Duration::minutes(t.m as i64)
+      + Duration::seconds(t.s as i64)
+}
+
+fn ending(special: &MoveSpecial) -> ErrStr<Ending> {
+   match special {
+      MoveSpecial::SpecialToryo => Ok(RESIGNS),
+      MoveSpecial::SpecialSennichite => Ok(SENNICHITE),
+      MoveSpecial::SpecialJishogi => Ok(JISHOGI),
+      MoveSpecial::SpecialTimeUp => Ok(TIMEUP),
+      MoveSpecial::SpecialTsumi => Ok(MATE),
+      MoveSpecial::SpecialChudan => Ok(ABANDONED),
+      _ => Err(format!("w7a has no result-line for {special:?}"))
+   }
+}
+
+// ----- COMMENTS -------------------------------------------------------
+
+// JKF comments are lines, a blank one between paragraphs, as KIF has them
+fn comment(comments: Option<&[String]>) -> Comment {
+   comments.and_then(|lines| paragraphs(lines))
+}
+
+// The converter's marks lead a move's comment lines: 封じ手, then 秒読み
+// (which the time control says again), then 王手 or 詰み (which the replay
+// finds again)
+const MARKS: [&str; 4] = ["封じ手", "秒読み", "王手", "詰み"];
+
+fn marks(comments: Option<&[String]>) -> (Vec<&str>, Option<&[String]>) {
+   let lines = comments.unwrap_or_default();
+   let count = lines.iter().take_while(|line| MARKS.contains(&line.as_str())).count();
+   let marks = lines[..count].iter().map(String::as_str).collect();
+   (marks, comments.map(|_| &lines[count..]))
+}
+
+// A comment is set off by blank lines, as are its paragraphs, each wrapped,
+// as the teu.ac.jp records are, at about WIDTH characters
+fn write_comment(f: &mut fmt::Formatter, comment: &Comment) -> fmt::Result {
+   if let Some(text) = comment {
+      for para in text.split("\n\n") {
+         writeln!(f)?;
+         for line in wrap(para, WIDTH) {
+            writeln!(f, "{line}")?;
+         }
+      }
+      writeln!(f)?;
+   }
+   Ok(())
+}
+
+/// Breaks the text into lines of at most width characters, at spaces
+pub fn wrap(text: &str, width: usize) -> Vec<String> {
+   let mut lines = Vec::new();
+   let mut line = String::new();
+   for word in text.split_whitespace() {
+      if !line.is_empty() && line.chars().count() + word.chars().count() >= width {
+         lines.push(line);
+         line = String::new();
+      }
+      if !line.is_empty() { line.push(' '); }
+      line.push_str(word);
+   }
+   if !line.is_empty() || lines.is_empty() { lines.push(line); }
+   lines
+}
+
+// ----- TESTS -------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   use shogi_kifu_converter::{converter::ToKif, jkf::MoveFormat, parser::parse_kif_str};
+
+   use book::file_utils::lines_from_file;
+
+   use crate::types::{check::marked, formats::to_jkf, w7a::load_w7a};
+
+   const OI: &str =
+      "../data/game_records/reijer_grimberger/2013-07-11-54th-oi-sen-game-1.w7a";
+
+   #[test]
+   fn test_english_date() -> ErrStr<()> {
+      assert_eq!("June 4th 2013", english_date("2013/06/04", None)?);
+      let end = "2013/07/11 18:21:00".to_string();
+      assert_eq!("July 10th and 11th 2013",
+                 english_date("2013/07/10 09:00:00", Some(&end))?);
+      assert_eq!("April 21st 1967", english_date("1967/04/21(金)", None)?);
+      Ok(())
+   }
+
+   #[test]
+   fn test_ordinal() {
+      let ords: Vec<String> = [1, 2, 3, 4, 11, 12, 13, 22, 31].into_iter()
+                                                              .map(ordinal)
+                                                              .collect();
+      assert_eq!(vec!["1st", "2nd", "3rd", "4th", "11th", "12th", "13th",
+                      "22nd", "31st"], ords);
+   }
+
+   #[test]
+   fn test_move_line() {
+      let b = Duration::minutes(10);
+      let w = Duration::minutes(11);
+      assert_eq!("10.B2bx7g+   00:10:00  00:11:00", move_line("10.B2bx7g+", (b, w)));
+      assert_eq!("Resigns      00:10:00  00:11:00", move_line("Resigns", (b, w)));
+   }
+
+   #[test]
+   fn test_wrap() {
+      let text = "The proper order. If white plays this knight before pushing";
+      assert_eq!(vec!["The proper order. If white", "plays this knight before",
+                      "pushing"], wrap(text, 27));
+   }
+
+   // a move line's columns, the notation without its check mark, which the
+   // writer adds and the record need not have
+   fn unmarked(line: &str) -> Vec<&str> {
+      line.split_whitespace().enumerate()
+          .map(|(i, col)| if i == 0 { marked(col).0 } else { col })
+          .collect()
+   }
+
+   // w7a to KIF and back again: the move lines must come out as they went in,
+   // but for the checks marked
+   #[test]
+   fn test_round_trip_through_kif() -> ErrStr<()> {
+      let kif = to_jkf(&load_w7a(OI)?)?.kifu().to_kif_owned();
+      let jkf = parse_kif_str(&kif).map_err(|e| format!("{e}"))?;
+      let w7a = to_w7a(&jkf)?;
+      let original: Vec<String> =
+         lines_from_file(OI)?.into_iter()
+                             .filter(|l| l.starts_with(|c: char| c.is_ascii_digit())
+                                         || l.starts_with("Resigns"))
+                             .filter(|l| l.contains(":"))
+                             .collect();
+      let written: Vec<&str> = w7a.lines().filter(|l| l.contains("  0")).collect();
+      assert_eq!(original.iter().map(|l| unmarked(l)).collect::<Vec<_>>(),
+                 written.iter().map(|l| unmarked(l)).collect::<Vec<_>>());
+      assert!(w7a.contains("\n69.G*3bch    07:10:00  07:14:00\n"));
+      assert!(w7a.starts_with("[Black \"Habu Yoshiharu, Oi\"]\n"));
+      assert!(w7a.contains("[Date \"July 10th and 11th 2013\"]"));
+      assert!(w7a.contains("Namekata seals the move for the first time"));
+      assert!(kif.contains("終了日時：2013/07/11"));
+      Ok(())
+   }
+
+   #[test]
+   fn test_sealed_round_trip() -> ErrStr<()> {
+      let game: W7A = "[Date \"July 10th to 12th 2013\"]\n\
+                       1.P7g-7f     00:00:00  00:00:00\n\
+                       2.P3c-3d     00:00:00  00:00:00\n\
+                       Sealed move\n\
+                       3.P2g-2f     00:00:00  00:00:00\n".parse()?;
+      let kif = to_jkf(&game)?.kifu().to_kif_owned();
+      assert!(kif.contains("終了日時：2013/07/11") && kif.contains("*封じ手"));
+      let w7a = to_w7a(&parse_kif_str(&kif).map_err(|e| format!("{e}"))?)?;
+      assert!(w7a.contains("2.P3c-3d     00:00:00  00:00:00\nSealed move\n"));
+      Ok(())
+   }
+
+   #[test]
+   fn test_moves_numbered_past_entries_without_one() -> ErrStr<()> {
+      let game: W7A = "1.P7g-7f     00:00:00  00:00:00\n\
+                       2.P3c-3d     00:00:00  00:00:00\n\
+                       3.P2g-2f     00:00:00  00:00:00\n".parse()?;
+      let mut jkf = parse_kif_str(&to_jkf(&game)?.kifu().to_kif_owned())
+                       .map_err(|e| format!("{e}"))?;
+      let aside = MoveFormat { comments: Some(vec!["An aside".to_string()]),
+                               ..MoveFormat::default() };
+      jkf.moves.insert(2, aside.clone());
+      jkf.moves.insert(1, MoveFormat { comments: Some(vec!["A preface".to_string()]),
+                                       ..aside });
+      let read = from_jkf(&jkf)?;
+      let ns: Vec<usize> = read.moves.iter().map(|m| m.n).collect();
+      assert_eq!(vec![1, 2, 3], ns);
+      assert_eq!(Some("An aside"), read.moves[0].comment.as_deref());
+      assert_eq!(Some("A preface"), read.comment());
+      Ok(())
+   }
+
+   #[test]
+   fn test_sfen_round_trip() -> ErrStr<()> {
+      let game: W7A = "[SFEN \"8k/9/7+P1/9/9/9/9/9/K8 b 2Gp 1\"]\n\
+                       1.K9i-8h     00:00:00  00:00:00\n\
+                       2.P*5e       00:00:00  00:00:00\n".parse()?;
+      let kif = to_jkf(&game)?.kifu().to_kif_owned();
+      let jkf = parse_kif_str(&kif).map_err(|e| format!("{e}"))?;
+      assert_eq!(game, from_jkf(&jkf)?);
+      Ok(())
+   }
+
+   #[test]
+   fn test_handicap_round_trip() -> ErrStr<()> {
+      let game: W7A = "[Black \"Shitate\"]\n\
+                       [Handicap \"Rook\"]\n\
+                       1.P3c-3d     00:00:00  00:01:00\n\
+                       2.P7g-7f     00:02:00  00:01:00\n".parse()?;
+      let kif = to_jkf(&game)?.kifu().to_kif_owned();
+      assert!(kif.contains("手合割：飛車落ち"));
+      let jkf = parse_kif_str(&kif).map_err(|e| format!("{e}"))?;
+      assert_eq!(game, from_jkf(&jkf)?);
+      Ok(())
+   }
+
+   #[test]
+   fn test_time_control_round_trip() -> ErrStr<()> {
+      let game: W7A = "[Black \"Habu\"]\n\
+                       [TimeControl \"10m+30s\"]\n\
+                       1.P7g-7f     00:09:00  00:00:00\n\
+                       2.P3c-3d     00:09:00  00:01:00\n\
+                       3.P2g-2f     00:10:20  00:01:00\n".parse()?;
+      let kif = to_jkf(&game)?.kifu().to_kif_owned();
+      let jkf = parse_kif_str(&kif).map_err(|e| format!("{e}"))?;
+      assert_eq!(game, from_jkf(&jkf)?);
+      Ok(())
+   }
+
+   #[test]
+   fn test_canonical_oi_game() -> ErrStr<()> {
+      let game = load_w7a(OI)?;
+      let text = canonical(&game)?;
+      let lines: Vec<String> = text.lines().map(String::from).collect();
+      let again = scan_w7a(&lines)?;
+      assert_eq!(game, again);
+      assert_eq!(text, canonical(&again)?);
+      assert!(text.contains("\n1.P7g-7f     00:00:00  00:00:00\n"));
+      assert!(text.lines().all(|line| line.chars().count() <= WIDTH));
+      Ok(())
+   }
+}